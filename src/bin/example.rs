@@ -4,7 +4,6 @@
 //! It demonstrates how to set up a provider, configure pool synchronization, and execute the sync process.
 use anyhow::Result;
 use pool_sync::{Chain, PoolSync, PoolType};
-use env_logger;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -17,12 +16,15 @@ async fn main() -> Result<()> {
         .build()?;
 
     // Synchronize pools
-    let (pools, last_synced_block) = pool_sync.sync_pools().await?;
-    println!(
-        "Synced {} pools up to block {}!",
-        pools.len(),
-        last_synced_block
-    );
+    let report = pool_sync.sync_pools().await?;
+    for (chain, chain_report) in &report.chains {
+        println!(
+            "Synced {} {} pools up to block {}!",
+            chain_report.pools.len(),
+            chain,
+            chain_report.last_synced_block()
+        );
+    }
 
     Ok(())
 }
@@ -2,6 +2,7 @@
 //!
 //! This module provides a builder pattern for constructing a PoolSync instance,
 //! allowing for flexible configuration of pool types and chains to be synced.
+//! Every chain is described by a `ChainConfig` with its own pool types and providers.
 
 use crate::pools::pool_fetchers::{
     AerodromeFetcher, AlienBaseV2Fetcher, AlienBaseV3Fetcher, BalancerV2Fetcher, BaseSwapV2Fetcher,
@@ -12,25 +13,59 @@ use crate::pools::pool_fetchers::{
 };
 
 use crate::errors::*;
+use crate::endpoints::{pool_provider, EndpointPool};
+use crate::limiter::{limit_transport, RpcLimiter};
 use crate::pools::*;
-use crate::{Chain, PoolSync, PoolType};
-use std::collections::HashMap;
-use std::sync::Arc;
+use crate::store::{FileStore, MemoryStore, PoolStore};
+use crate::{Chain, ChainSync, PoolSync, PoolType, TokenRegistry};
+use alloy::network::AnyNetwork;
+use alloy::providers::{Provider, ProviderBuilder, RootProvider};
+use alloy::transports::http::{Client, Http};
+use alloy::transports::{BoxTransport, Transport};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-/// Builder for constructing a PoolSync instance
+/// Pool types and providers to sync on a single chain
 #[derive(Default)]
-pub struct PoolSyncBuilder {
-    /// Mapping from the pool type to the implementation of its fetcher
-    fetchers: HashMap<PoolType, Arc<dyn PoolFetcher>>,
+pub struct ChainConfig {
     /// The chain to be synced on
     chain: Option<Chain>,
-    /// Rate limit on the rpc endpoint
-    rate_limit: Option<usize>,
+    /// Mapping from the pool type to the implementation of its fetcher
+    fetchers: HashMap<PoolType, Arc<dyn PoolFetcher>>,
+    /// User defined start blocks overriding the factory deployment blocks
+    start_blocks: HashMap<PoolType, u64>,
+    /// Weighted endpoints for the archive node
+    archive: Vec<Endpoint>,
+    /// Weighted endpoints for the full node
+    full: Vec<Endpoint>,
 }
 
-impl PoolSyncBuilder {
+/// Transport of an endpoint with its key and weight
+type Endpoint = (EndpointKey, BoxTransport, u32);
+
+/// Identifies an endpoint across roles, so that it is only limited once
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum EndpointKey {
+    /// Http endpoint, the same url added twice is the same endpoint
+    Url(String),
+    /// Any other transport, unique per provider that was added
+    Provider(usize),
+}
+
+impl ChainConfig {
+    /// Creates an empty configuration for the chain
+    pub fn new(chain: Chain) -> Self {
+        Self {
+            chain: Some(chain),
+            ..Default::default()
+        }
+    }
+
     /// Adds a new pool type to be synced
-    /// The builder instance for method chaining
+    /// The config instance for method chaining
     pub fn add_pool(mut self, pool_type: PoolType) -> Self {
         match pool_type {
             PoolType::UniswapV2 => {
@@ -129,41 +164,491 @@ impl PoolSyncBuilder {
         self
     }
 
+    /// Override the block a pool type starts syncing from
+    ///
+    /// By default pools are synced from the deployment block of the protocol factory.
+    /// This only applies when there is no cache for the pool type yet.
+    /// The config instance for method chaining
+    pub fn start_block(mut self, pool_type: PoolType, block: u64) -> Self {
+        self.start_blocks.insert(pool_type, block);
+        self
+    }
+
+    /// Add a provider used for historical queries, this must be an archive node
+    ///
+    /// Adding several providers spreads the requests over them and fails over
+    /// when one of them stops responding.
+    /// The config instance for method chaining
+    pub fn archive_provider<T>(self, provider: RootProvider<T, AnyNetwork>) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.archive_endpoint(provider, 1)
+    }
+
+    /// Add an archive provider that receives requests in proportion to its weight
+    /// The config instance for method chaining
+    pub fn archive_endpoint<T>(mut self, provider: RootProvider<T, AnyNetwork>, weight: u32) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.archive.push(endpoint(transport_of(provider), weight));
+        self
+    }
+
+    /// Add a provider used for contract calls and following the chain head
+    /// The config instance for method chaining
+    pub fn full_provider<T>(self, provider: RootProvider<T, AnyNetwork>) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.full_endpoint(provider, 1)
+    }
+
+    /// Add a full node provider that receives requests in proportion to its weight
+    /// The config instance for method chaining
+    pub fn full_endpoint<T>(mut self, provider: RootProvider<T, AnyNetwork>, weight: u32) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.full.push(endpoint(transport_of(provider), weight));
+        self
+    }
+
+    /// Use a single provider for both the archive and the full node role
+    /// The config instance for method chaining
+    pub fn provider<T>(mut self, provider: RootProvider<T, AnyNetwork>) -> Self
+    where
+        T: Transport + Clone,
+    {
+        let transport = transport_of(provider);
+        let endpoint = endpoint(transport, 1);
+        self.archive.push(endpoint.clone());
+        self.full.push(endpoint);
+        self
+    }
+
+    /// Returns true if nothing has been configured
+    fn is_empty(&self) -> bool {
+        self.chain.is_none()
+            && self.fetchers.is_empty()
+            && self.start_blocks.is_empty()
+            && self.archive.is_empty()
+            && self.full.is_empty()
+    }
+
+    /// Validates the configuration and resolves the start blocks and providers
+    ///
+    /// When it is the only chain, providers may also come from the unprefixed
+    /// `ARCHIVE` and `FULL` environment variables. Each endpoint gets its own limiter, an
+    /// endpoint serving both roles shares it between them at the lower of the two limits.
+    fn build(
+        self,
+        only_chain: bool,
+        limits: &RateLimits,
+        tokens: &Arc<Mutex<TokenRegistry>>,
+        store: &Arc<dyn PoolStore>,
+    ) -> Result<ChainSync, PoolSyncError> {
+        // Ensure the chain is set
+        let chain = self.chain.ok_or(PoolSyncError::ChainNotSet)?;
+
+        // Ensure all the pools are supported
+        for pool_type in self.fetchers.keys() {
+            if !chain.supported(pool_type) {
+                return Err(PoolSyncError::UnsupportedPoolType);
+            }
+        }
+
+        // start from the factory deployment unless the user specified otherwise
+        let start_blocks = self
+            .fetchers
+            .iter()
+            .map(|(pool_type, fetcher)| {
+                let start_block = self
+                    .start_blocks
+                    .get(pool_type)
+                    .copied()
+                    .unwrap_or_else(|| fetcher.deployment_block(chain));
+                (*pool_type, start_block)
+            })
+            .collect();
+
+        // fall back to the http endpoints in the environment for any role without a provider
+        let archive = match self.archive.is_empty() {
+            false => self.archive,
+            true => endpoints_from_env(chain, "ARCHIVE", only_chain)?,
+        };
+        let full = match self.full.is_empty() {
+            false => self.full,
+            true => endpoints_from_env(chain, "FULL", only_chain)?,
+        };
+
+        // every request to an endpoint goes through its own token bucket, whatever role
+        // it was sent for
+        let mut quotas: HashMap<EndpointKey, u64> = HashMap::new();
+        for (endpoints, per_second) in [(&archive, limits.archive), (&full, limits.full)] {
+            for (key, _, _) in endpoints {
+                let quota = quotas.entry(key.clone()).or_insert(per_second);
+                *quota = (*quota).min(per_second);
+            }
+        }
+        let limiters: HashMap<EndpointKey, Arc<RpcLimiter>> = quotas
+            .into_iter()
+            .map(|(key, per_second)| {
+                (key, Arc::new(RpcLimiter::new(per_second, limits.weights.clone())))
+            })
+            .collect();
+        let limit = |endpoints: Vec<Endpoint>| {
+            let endpoints = endpoints
+                .into_iter()
+                .map(|(key, transport, weight)| {
+                    (limit_transport(transport, limiters[&key].clone()), weight)
+                })
+                .collect();
+            EndpointPool::new(endpoints)
+        };
+        let archive_endpoints = limit(archive);
+        let full_endpoints = limit(full);
+
+        Ok(ChainSync {
+            chain,
+            fetchers: self.fetchers,
+            start_blocks,
+            archive: Arc::new(pool_provider(&archive_endpoints)),
+            archive_endpoints,
+            full_endpoints,
+            tokens: tokens.clone(),
+            store: store.clone(),
+        })
+    }
+}
+
+/// Request budgets applied to the providers of every chain
+struct RateLimits {
+    archive: u64,
+    full: u64,
+    weights: HashMap<String, u64>,
+}
+
+/// Builder for constructing a PoolSync instance
+///
+/// The pool, start block and provider methods configure the chain set with `chain`.
+/// Further chains are added with `add_chain`.
+#[derive(Default)]
+pub struct PoolSyncBuilder {
+    /// Configuration of the chain set directly on the builder
+    primary: ChainConfig,
+    /// Additional chains to be synced
+    chains: Vec<ChainConfig>,
+    /// Rate limit on the rpc endpoints
+    rate_limit: Option<usize>,
+    /// Rate limit on the archive endpoints, overrides the general rate limit
+    archive_rate_limit: Option<usize>,
+    /// Rate limit on the full node endpoints, overrides the general rate limit
+    full_rate_limit: Option<usize>,
+    /// Compute unit cost of rpc methods, methods without a weight cost one unit
+    compute_units: HashMap<String, u64>,
+    /// Interval at which the live sync polls for new blocks
+    poll_interval: Option<Duration>,
+    /// Where the pools and sync state are persisted
+    store: Option<Arc<dyn PoolStore>>,
+    /// Directory of the default file store
+    cache_dir: Option<PathBuf>,
+    /// Namespace of the caches in the default file store
+    profile: Option<String>,
+    /// Whether anything is persisted at all
+    persist: Option<bool>,
+}
+
+impl PoolSyncBuilder {
+    /// Adds a new pool type to be synced
+    /// The builder instance for method chaining
+    pub fn add_pool(mut self, pool_type: PoolType) -> Self {
+        self.primary = self.primary.add_pool(pool_type);
+        self
+    }
+
+    /// Add multiple pools to be synced
+    pub fn add_pools(mut self, pools: &[PoolType]) -> Self {
+        self.primary = self.primary.add_pools(pools);
+        self
+    }
+
     /// Sets the chain to sync on
     /// The builder instance for method chaining
     pub fn chain(mut self, chain: Chain) -> Self {
-        self.chain = Some(chain);
+        self.primary.chain = Some(chain);
         self
     }
 
-    /// Set the rate limit of the rpc
+    /// Adds another chain with its own pool types and providers
+    /// The builder instance for method chaining
+    pub fn add_chain(mut self, config: ChainConfig) -> Self {
+        self.chains.push(config);
+        self
+    }
+
+    /// Set the requests per second allowed on every endpoint
+    ///
+    /// Each archive and full node gets its own token bucket of this size that every
+    /// request to it draws from. This also bounds the number of requests in flight.
     /// The builder instance for method chaining
     pub fn rate_limit(mut self, rate_limit: usize) -> Self {
         self.rate_limit = Some(rate_limit);
         self
     }
 
+    /// Set the requests per second allowed on the archive endpoints
+    /// The builder instance for method chaining
+    pub fn archive_rate_limit(mut self, rate_limit: usize) -> Self {
+        self.archive_rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Set the requests per second allowed on the full node endpoints
+    /// The builder instance for method chaining
+    pub fn full_rate_limit(mut self, rate_limit: usize) -> Self {
+        self.full_rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Set the compute unit cost of an rpc method, e.g. `eth_getLogs`
+    ///
+    /// Once weights are set the rate limits are counted in compute units per second
+    /// instead of requests. Methods without a weight cost one unit.
+    /// The builder instance for method chaining
+    pub fn compute_units(mut self, method: &str, units: u64) -> Self {
+        self.compute_units.insert(method.to_string(), units);
+        self
+    }
+
+    /// Set how often the live sync checks for a new block
+    /// The builder instance for method chaining
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// Set where the pools and sync state are persisted, cache files in `cache/` by default
+    /// The builder instance for method chaining
+    pub fn store(mut self, store: impl PoolStore + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Set the directory the cache files are kept in, `cache/` in the working directory by default
+    ///
+    /// Only applies when no store is set.
+    /// The builder instance for method chaining
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Keep the caches of this configuration apart from the others in the same directory
+    ///
+    /// Syncs with different profiles never read or overwrite each other's caches, which is
+    /// needed when the same chain is synced with different settings. Only applies when no
+    /// store is set.
+    /// The builder instance for method chaining
+    pub fn profile(mut self, profile: &str) -> Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    /// Set whether the pools and sync state are persisted at all
+    ///
+    /// Without persistence every sync starts from scratch and nothing is written to disk,
+    /// which suits tests and other short lived runs.
+    /// The builder instance for method chaining
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = Some(persist);
+        self
+    }
+
+    /// Override the block a pool type starts syncing from
+    ///
+    /// By default pools are synced from the deployment block of the protocol factory.
+    /// This only applies when there is no cache for the pool type yet.
+    /// The builder instance for method chaining
+    pub fn start_block(mut self, pool_type: PoolType, block: u64) -> Self {
+        self.primary = self.primary.start_block(pool_type, block);
+        self
+    }
+
+    /// Add a provider used for historical queries, this must be an archive node
+    /// The builder instance for method chaining
+    pub fn archive_provider<T>(mut self, provider: RootProvider<T, AnyNetwork>) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.primary = self.primary.archive_provider(provider);
+        self
+    }
+
+    /// Add an archive provider that receives requests in proportion to its weight
+    /// The builder instance for method chaining
+    pub fn archive_endpoint<T>(mut self, provider: RootProvider<T, AnyNetwork>, weight: u32) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.primary = self.primary.archive_endpoint(provider, weight);
+        self
+    }
+
+    /// Add a provider used for contract calls and following the chain head
+    /// The builder instance for method chaining
+    pub fn full_provider<T>(mut self, provider: RootProvider<T, AnyNetwork>) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.primary = self.primary.full_provider(provider);
+        self
+    }
+
+    /// Add a full node provider that receives requests in proportion to its weight
+    /// The builder instance for method chaining
+    pub fn full_endpoint<T>(mut self, provider: RootProvider<T, AnyNetwork>, weight: u32) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.primary = self.primary.full_endpoint(provider, weight);
+        self
+    }
+
+    /// Use a single provider for both the archive and the full node role
+    /// The builder instance for method chaining
+    pub fn provider<T>(mut self, provider: RootProvider<T, AnyNetwork>) -> Self
+    where
+        T: Transport + Clone,
+    {
+        self.primary = self.primary.provider(provider);
+        self
+    }
+
     /// Consumes the builder and produces a constructed PoolSync
     pub fn build(self) -> Result<PoolSync, PoolSyncError> {
-        // Ensure the chain is set
-        let chain = self.chain.ok_or(PoolSyncError::ChainNotSet)?;
+        // the primary chain only counts if something was configured on it
+        let mut configs = self.chains;
+        if !self.primary.is_empty() {
+            configs.insert(0, self.primary);
+        }
+        if configs.is_empty() {
+            return Err(PoolSyncError::ChainNotSet);
+        }
 
-        // Ensure all the pools are supported
-        for pool_type in self.fetchers.keys() {
-            if !chain.supported(pool_type) {
-                return Err(PoolSyncError::UnsupportedPoolType);
+        // Ensure every chain is only configured once
+        let mut seen = HashSet::new();
+        for config in &configs {
+            if let Some(chain) = config.chain {
+                if !seen.insert(chain) {
+                    return Err(PoolSyncError::DuplicateChain(chain));
+                }
             }
         }
 
         // set rate limit to user defined if specified, otherwise set high value
         // that will not be hit to simulate unlimited requests
         let rate_limit = self.rate_limit.unwrap_or(10000) as u64;
+        let limits = RateLimits {
+            archive: self.archive_rate_limit.map_or(rate_limit, |limit| limit as u64),
+            full: self.full_rate_limit.map_or(rate_limit, |limit| limit as u64),
+            weights: self.compute_units,
+        };
+
+        // every chain registers its tokens in the same registry and shares the store
+        let tokens = Arc::new(Mutex::new(TokenRegistry::default()));
+        let store: Arc<dyn PoolStore> = match (self.store, self.persist.unwrap_or(true)) {
+            (_, false) => Arc::new(MemoryStore::new()),
+            (Some(store), true) => store,
+            (None, true) => {
+                let mut file_store = match self.cache_dir {
+                    Some(cache_dir) => FileStore::new(cache_dir),
+                    None => FileStore::default(),
+                };
+                if let Some(profile) = &self.profile {
+                    file_store = file_store.profile(profile);
+                }
+                Arc::new(file_store)
+            }
+        };
+        let only_chain = configs.len() == 1;
+        let chains = configs
+            .into_iter()
+            .map(|config| {
+                config
+                    .build(only_chain, &limits, &tokens, &store)
+                    .map(|sync| (sync.chain, sync))
+            })
+            .collect::<Result<_, _>>()?;
+
+        // poll about once per block on the fastest supported chain by default
+        let poll_interval = self.poll_interval.unwrap_or(Duration::from_secs(2));
 
         // Construct PoolSync
         Ok(PoolSync {
-            fetchers: self.fetchers,
+            chains,
             rate_limit,
-            chain,
+            poll_interval,
+            tokens,
         })
     }
 }
+
+/// Boxes the provider and takes its transport so it can join an endpoint pool
+fn transport_of<T>(provider: RootProvider<T, AnyNetwork>) -> BoxTransport
+where
+    T: Transport + Clone,
+{
+    provider.boxed().client().transport().clone()
+}
+
+/// Keys the transport by its url if it is an http endpoint
+fn endpoint(transport: BoxTransport, weight: u32) -> Endpoint {
+    static PROVIDERS: AtomicUsize = AtomicUsize::new(0);
+    let key = match endpoint_url(&transport) {
+        Some(url) => EndpointKey::Url(url),
+        None => EndpointKey::Provider(PROVIDERS.fetch_add(1, Ordering::Relaxed)),
+    };
+    (key, transport, weight)
+}
+
+fn endpoint_url(transport: &BoxTransport) -> Option<String> {
+    let transport = transport.as_any();
+    if let Some(http) = transport.downcast_ref::<Http<Client>>() {
+        return Some(http.url().to_string());
+    }
+    transport.downcast_ref::<BoxTransport>().and_then(endpoint_url)
+}
+
+/// Builds http endpoints from the urls stored in the environment
+///
+/// The chain specific variable, e.g. `BASE_ARCHIVE`, is preferred. The plain role
+/// variable is only used when a single chain is synced. Several endpoints can be
+/// given as a comma separated list, they all get the same weight.
+fn endpoints_from_env(
+    chain: Chain,
+    role: &'static str,
+    only_chain: bool,
+) -> Result<Vec<Endpoint>, PoolSyncError> {
+    dotenv::dotenv().ok();
+    let chain_var = format!("{}_{}", chain.to_string().to_uppercase(), role);
+    let not_set = || PoolSyncError::ProviderNotSet {
+        chain,
+        role,
+        env_var: chain_var.clone(),
+    };
+    let urls = std::env::var(&chain_var)
+        .ok()
+        .or_else(|| only_chain.then(|| std::env::var(role).ok()).flatten())
+        .ok_or_else(not_set)?;
+    urls.split(',')
+        .map(|url| {
+            let url = url.trim().parse().map_err(|_| not_set())?;
+            let provider = ProviderBuilder::new()
+                .network::<AnyNetwork>()
+                .on_http(url);
+            Ok(endpoint(transport_of(provider), 1))
+        })
+        .collect()
+}
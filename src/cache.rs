@@ -1,63 +1,114 @@
 //! Pool Synchronization Cache Implementation
 //!
-//! This module provides functionality for caching pool synchronization data,
-//! including structures and functions for reading from and writing to cache files.
+//! This module defines the state that is kept for every synced pool type. How it is
+//! persisted is up to the `PoolStore` the sync was built with.
 //!
-use crate::chain::Chain;
-use crate::pools::{Pool, PoolType};
-use anyhow::{Context, Result};
+use crate::pools::{Pool, PoolInfo, PoolType};
+use crate::reorg::{BlockWindow, UndoJournal};
+use alloy::primitives::Address;
 use serde::{Deserialize, Serialize};
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::PathBuf;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Pools and sync state of a single pool type on a chain
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PoolCache {
     pub last_synced_block: u64,
     pub pool_type: PoolType,
     pub pools: Vec<Pool>,
     pub is_initial_sync: bool,
+    /// Hashes of the recently synced blocks, used to detect reorgs
+    #[serde(default)]
+    pub block_window: BlockWindow,
+    /// Undo information for the blocks that can still be reorged
+    #[serde(default)]
+    pub journal: UndoJournal,
+    /// Discovered pools that could not be built yet, retried on every sync
+    #[serde(default)]
+    pub pending_pools: Vec<PendingPool>,
+    /// Progress of a sync that was interrupted before reaching its end block
+    #[serde(default)]
+    pub checkpoint: Option<SyncCheckpoint>,
+    /// Pools changed since the cache was last stored
+    #[serde(skip)]
+    pub dirty: HashSet<Address>,
+    /// Pools dropped since the cache was last stored
+    #[serde(skip)]
+    pub removed: HashSet<Address>,
+    /// Damaged cache file that was set aside when the store loaded the previous generation
+    #[serde(skip)]
+    pub recovered_from: Option<PathBuf>,
 }
 
-pub fn read_cache_file(pool_type: &PoolType, chain: Chain) -> Result<PoolCache> {
-    let pool_cache_file = format!("cache/{}_{}_cache.json", chain, pool_type);
-    if Path::new(&pool_cache_file).exists() {
-        let file = File::open(&pool_cache_file)
-            .with_context(|| format!("Failed to open cache file: {}", pool_cache_file))?;
-        let reader = BufReader::new(file);
-        let pool_cache: PoolCache = serde_json::from_reader(reader).with_context(|| {
-            format!("Failed to deserialize cache from file: {}", pool_cache_file)
-        })?;
-        Ok(pool_cache)
-    } else {
-        if Chain::Base == chain {
-            Ok(PoolCache {
-                last_synced_block: 0,
-                pool_type: *pool_type,
-                pools: Vec::new(),
-                is_initial_sync: true,
-            })
-        } else {
-            Ok(PoolCache {
-                last_synced_block: 9_999_999,
-                pool_type: *pool_type,
-                pools: Vec::new(),
-                is_initial_sync: true,
-            })
+impl PoolCache {
+    /// Empty cache, the first sync begins at the start block
+    pub fn new(pool_type: PoolType, start_block: u64) -> Self {
+        Self {
+            last_synced_block: start_block.saturating_sub(1),
+            pool_type,
+            pools: Vec::new(),
+            is_initial_sync: true,
+            block_window: BlockWindow::default(),
+            journal: UndoJournal::default(),
+            pending_pools: Vec::new(),
+            checkpoint: None,
+            dirty: HashSet::new(),
+            removed: HashSet::new(),
+            recovered_from: None,
         }
     }
+
+    /// Marks the pools as changed, the ones that are no longer in the cache as dropped
+    pub(crate) fn mark_changed(&mut self, addresses: impl IntoIterator<Item = Address>) {
+        let present: HashSet<Address> = self.pools.iter().map(|pool| pool.address()).collect();
+        for address in addresses {
+            if present.contains(&address) {
+                self.removed.remove(&address);
+                self.dirty.insert(address);
+            } else {
+                self.dirty.remove(&address);
+                self.removed.insert(address);
+            }
+        }
+    }
+}
+
+/// A discovered pool whose data could not be fetched
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct PendingPool {
+    pub address: Address,
+    /// First block of the range the pool was discovered in, its logs are replayed from here
+    pub since_block: u64,
+}
+
+/// Progress of an interrupted sync, the next sync resumes from here
+///
+/// Checkpoints are only taken while syncing blocks that are too deep to be reorged, so
+/// the range does not need to be checked again when resuming.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncCheckpoint {
+    /// Block the interrupted sync was heading for
+    pub end_block: u64,
+    /// Last phase of the sync that was completed
+    pub phase: SyncPhase,
 }
 
-pub fn write_cache_file(pool_cache: &PoolCache, chain: Chain) -> Result<()> {
-    let pool_cache_file = format!("cache/{}_{}_cache.json", chain, pool_cache.pool_type);
-    let file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&pool_cache_file)
-        .with_context(|| format!("Failed to create or open cache file: {}", pool_cache_file))?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer(writer, &pool_cache)
-        .with_context(|| format!("Failed to serialize cache to file: {}", pool_cache_file))?;
-    Ok(())
+/// Phases of syncing a cache over a block range
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SyncPhase {
+    /// Nothing has been fetched yet
+    Started,
+    /// The addresses of the pools created in the range are known
+    Discovered { pool_addrs: Vec<Address> },
+    /// The new pools are built and all logs before `next_block` are applied
+    Populating {
+        new_pools: Vec<Pool>,
+        /// Pools bootstrapped from a snapshot at the end block, no logs are applied to them
+        #[serde(default)]
+        snapshot_pools: Vec<Pool>,
+        retried_pools: Vec<Pool>,
+        failed: Vec<Address>,
+        still_pending: Vec<Address>,
+        next_block: u64,
+    },
 }
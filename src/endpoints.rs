@@ -0,0 +1,333 @@
+//! RPC Endpoint Pools
+//!
+//! This module spreads the requests of a provider role over several endpoints. Requests
+//! are distributed with a smooth weighted round robin where the configured weight of an
+//! endpoint is scaled by its recent success rate and latency. A request that fails on the
+//! transport level or is answered with a rate limit or internal error is retried on the
+//! next endpoint, and endpoints that keep failing are skipped for a cooldown period.
+//!
+//! Endpoints can be at slightly different heights, so the head, the block hashes and the
+//! reorg checks of a sync pass are sent to one pinned endpoint.
+//!
+//! The largest get_logs block range an endpoint accepts is learned from its rejections and
+//! kept for the lifetime of the pool, larger ranges are routed to the other endpoints.
+
+use alloy::providers::RootProvider;
+use alloy::rpc::client::RpcClient;
+use alloy::rpc::json_rpc::{ErrorPayload, RequestPacket, ResponsePacket};
+use alloy::transports::{BoxTransport, Transport, TransportError, TransportFut};
+use log::warn;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::Service;
+
+use crate::rpc::{log_limit, LogLimit};
+use crate::SyncProvider;
+
+/// Json rpc error codes that are a failure of the endpoint rather than of the request
+const ENDPOINT_ERROR_CODES: &[i64] = &[
+    -32603, // internal error
+    -32005, // limit exceeded
+    429,
+];
+/// Error messages of providers that are rate limiting or overloaded
+const ENDPOINT_ERROR_MESSAGES: &[&str] = &[
+    "rate limit",
+    "too many requests",
+    "compute units",
+    "capacity",
+    "internal error",
+];
+
+/// Consecutive failures after which an endpoint is put on cooldown
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+/// How long an endpoint that keeps failing is skipped
+const COOLDOWN: Duration = Duration::from_secs(30);
+/// Weight of the newest sample in the success rate and latency averages
+const EWMA_ALPHA: f64 = 0.2;
+/// Latency below which endpoints are considered equally fast
+const MIN_LATENCY: Duration = Duration::from_millis(10);
+
+/// Health of a single endpoint, updated after every request
+#[derive(Debug, Clone)]
+pub struct EndpointHealth {
+    /// Moving average of the share of successful requests
+    pub success_rate: f64,
+    /// Moving average of the response time of successful requests
+    pub latency: Duration,
+    /// Failures since the last successful request
+    pub consecutive_failures: u32,
+    /// The endpoint is skipped until this instant
+    pub cooldown_until: Option<Instant>,
+    /// Largest get_logs block range the endpoint accepts, once it rejected a range
+    pub log_range_cap: Option<u64>,
+}
+
+impl Default for EndpointHealth {
+    fn default() -> Self {
+        Self {
+            success_rate: 1.0,
+            latency: MIN_LATENCY,
+            consecutive_failures: 0,
+            cooldown_until: None,
+            log_range_cap: None,
+        }
+    }
+}
+
+impl EndpointHealth {
+    fn record_success(&mut self, latency: Duration) {
+        self.success_rate = self.success_rate * (1.0 - EWMA_ALPHA) + EWMA_ALPHA;
+        self.latency = self.latency.mul_f64(1.0 - EWMA_ALPHA) + latency.mul_f64(EWMA_ALPHA);
+        self.consecutive_failures = 0;
+        self.cooldown_until = None;
+    }
+
+    fn record_failure(&mut self) {
+        self.success_rate *= 1.0 - EWMA_ALPHA;
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            self.cooldown_until = Some(Instant::now() + COOLDOWN);
+        }
+    }
+
+    /// Remembers that the endpoint rejected a get_logs range of `span` blocks
+    fn record_log_range_limit(&mut self, span: u64) {
+        let cap = (span / 2).max(1);
+        self.log_range_cap = Some(self.log_range_cap.map_or(cap, |current| current.min(cap)));
+    }
+
+    fn accepts_log_range(&self, span: u64) -> bool {
+        self.log_range_cap.is_none_or(|cap| span <= cap)
+    }
+
+    fn is_cooling_down(&self, now: Instant) -> bool {
+        self.cooldown_until.is_some_and(|until| until > now)
+    }
+
+    /// Share of the requests the endpoint should receive relative to its weight
+    fn score(&self, weight: u32) -> f64 {
+        let latency = self.latency.max(MIN_LATENCY).as_secs_f64();
+        weight as f64 * self.success_rate.max(0.01) * MIN_LATENCY.as_secs_f64() / latency
+    }
+}
+
+struct Endpoint {
+    transport: BoxTransport,
+    weight: u32,
+}
+
+/// Selection state of the endpoints
+struct PoolState {
+    health: Vec<EndpointHealth>,
+    /// Running weights of the smooth weighted round robin
+    current: Vec<f64>,
+}
+
+/// Transport that distributes requests over a pool of endpoints
+#[derive(Clone)]
+pub struct EndpointPool {
+    endpoints: Arc<Vec<Endpoint>>,
+    state: Arc<Mutex<PoolState>>,
+    /// Endpoint that is tried first for every request
+    pin: Option<usize>,
+}
+
+impl EndpointPool {
+    /// Creates a pool of the transports with their weights
+    pub fn new(endpoints: Vec<(BoxTransport, u32)>) -> Self {
+        let endpoints: Vec<Endpoint> = endpoints
+            .into_iter()
+            .map(|(transport, weight)| Endpoint {
+                transport,
+                weight: weight.max(1),
+            })
+            .collect();
+        let state = PoolState {
+            health: vec![EndpointHealth::default(); endpoints.len()],
+            current: vec![0.0; endpoints.len()],
+        };
+        Self {
+            endpoints: Arc::new(endpoints),
+            state: Arc::new(Mutex::new(state)),
+            pin: None,
+        }
+    }
+
+    /// Returns a pool that sends every request to the healthiest endpoint first
+    ///
+    /// The other endpoints are only used when the pinned one fails, so the requests see
+    /// a consistent view of the chain.
+    pub fn pinned(&self) -> Self {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let pin = (0..self.endpoints.len())
+            .filter(|i| !state.health[*i].is_cooling_down(now))
+            .max_by(|a, b| {
+                let score = |i: usize| state.health[i].score(self.endpoints[i].weight);
+                score(*a).total_cmp(&score(*b))
+            });
+        Self {
+            endpoints: self.endpoints.clone(),
+            state: self.state.clone(),
+            pin: pin.or(self.pin),
+        }
+    }
+
+    /// Returns the current health of every endpoint
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.state.lock().unwrap().health.clone()
+    }
+
+    /// Largest get_logs block range that an endpoint which is not on cooldown accepts
+    pub fn log_range_cap(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let cap = |health: &EndpointHealth| health.log_range_cap.unwrap_or(u64::MAX);
+        state
+            .health
+            .iter()
+            .filter(|health| !health.is_cooling_down(now))
+            .map(cap)
+            .max()
+            .or_else(|| state.health.iter().map(cap).max())
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Picks the next endpoint that has not been tried for the request yet
+    ///
+    /// Endpoints on cooldown and endpoints that rejected get_logs ranges of the size of
+    /// `log_span` before are only used once every other endpoint was tried.
+    fn pick(&self, tried: &[bool], log_span: Option<u64>) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let untried = |i: &usize| !tried[*i];
+        let mut candidates: Vec<usize> = (0..self.endpoints.len())
+            .filter(untried)
+            .filter(|i| !state.health[*i].is_cooling_down(now))
+            .filter(|i| log_span.is_none_or(|span| state.health[*i].accepts_log_range(span)))
+            .collect();
+        if candidates.is_empty() {
+            candidates = (0..self.endpoints.len()).filter(untried).collect();
+        }
+
+        // smooth weighted round robin over the health scaled weights
+        let scores: Vec<f64> = candidates
+            .iter()
+            .map(|i| state.health[*i].score(self.endpoints[*i].weight))
+            .collect();
+        let total: f64 = scores.iter().sum();
+        for (i, score) in candidates.iter().zip(&scores) {
+            state.current[*i] += score;
+        }
+        let chosen = *candidates
+            .iter()
+            .max_by(|a, b| state.current[**a].total_cmp(&state.current[**b]))?;
+        state.current[chosen] -= total;
+        Some(chosen)
+    }
+
+    async fn send(self, request: RequestPacket) -> Result<ResponsePacket, TransportError> {
+        let mut tried = vec![false; self.endpoints.len()];
+        let mut last_result = None;
+        let log_span = log_span(&request);
+
+        loop {
+            let index = match self.pin {
+                Some(pin) if !tried[pin] => pin,
+                _ => match self.pick(&tried, log_span) {
+                    Some(index) => index,
+                    None => break,
+                },
+            };
+            tried[index] = true;
+            let mut transport = self.endpoints[index].transport.clone();
+            let started = Instant::now();
+            let result = transport.call(request.clone()).await;
+            let failure = match &result {
+                Ok(response) => response
+                    .iter_errors()
+                    .find(|e| is_endpoint_error(e))
+                    .map(|e| e.to_string()),
+                Err(e) => Some(e.to_string()),
+            };
+            match failure {
+                None => {
+                    let mut state = self.state.lock().unwrap();
+                    let health = &mut state.health[index];
+                    health.record_success(started.elapsed());
+                    if let (Some(span), Ok(response)) = (log_span, &result) {
+                        let range_limited = response
+                            .iter_errors()
+                            .any(|e| log_limit(&e.message) == Some(LogLimit::Range));
+                        if range_limited {
+                            health.record_log_range_limit(span);
+                        }
+                    }
+                    return result;
+                }
+                Some(e) => {
+                    if self.endpoints.len() > 1 {
+                        warn!("Rpc endpoint {} failed, failing over: {}", index, e);
+                    }
+                    self.state.lock().unwrap().health[index].record_failure();
+                    last_result = Some(result);
+                }
+            }
+        }
+
+        last_result.expect("endpoint pool is never empty")
+    }
+}
+
+impl Service<RequestPacket> for EndpointPool {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        Box::pin(self.clone().send(request))
+    }
+}
+
+/// Whether the error payload means the endpoint could not serve the request, reverts and
+/// other errors of the request itself are passed on to the caller
+fn is_endpoint_error(error: &ErrorPayload) -> bool {
+    let message = error.message.to_lowercase();
+    // -32005 is also used for get_logs requests that are too large, those are split by
+    // the caller and every endpoint would reject them the same way
+    if message.contains("revert") || log_limit(&message).is_some() {
+        return false;
+    }
+    ENDPOINT_ERROR_CODES.contains(&error.code)
+        || ENDPOINT_ERROR_MESSAGES
+            .iter()
+            .any(|pattern| message.contains(pattern))
+}
+
+/// Number of blocks requested by a single get_logs request with a numeric block range
+fn log_span(request: &RequestPacket) -> Option<u64> {
+    let RequestPacket::Single(request) = request else {
+        return None;
+    };
+    if request.method() != "eth_getLogs" {
+        return None;
+    }
+    let params: serde_json::Value = serde_json::from_str(request.params()?.get()).ok()?;
+    let block = |key: &str| {
+        let block = params[0][key].as_str()?;
+        u64::from_str_radix(block.trim_start_matches("0x"), 16).ok()
+    };
+    let (from_block, to_block) = (block("fromBlock")?, block("toBlock")?);
+    Some(to_block.checked_sub(from_block)? + 1)
+}
+
+/// Builds a provider that sends its requests to the pool of endpoints
+pub(crate) fn pool_provider(pool: &EndpointPool) -> SyncProvider {
+    RootProvider::new(RpcClient::new(pool.clone().boxed(), false))
+}
@@ -4,8 +4,13 @@
 //! It leverages the `thiserror` crate for deriving the `Error` trait and providing
 //! formatted error messages.
 
+use alloy::primitives::{Address, B256};
+use alloy::rpc::types::Log;
+use std::fmt::Display;
 use thiserror::Error;
 
+use crate::{Chain, PoolType, SyncReport};
+
 /// Enumerates the various error types that can occur during PoolSync operations
 #[derive(Error, Debug)]
 pub enum PoolSyncError {
@@ -13,6 +18,10 @@ pub enum PoolSyncError {
     #[error("Provider error: {0}")]
     ProviderError(String),
 
+    /// The sync was cancelled, its progress is kept in the cache
+    #[error("Sync was cancelled")]
+    Cancelled,
+
     /// Represents I/O errors that may occur during file operations
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
@@ -21,6 +30,14 @@ pub enum PoolSyncError {
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
 
+    /// Represents errors that occur while reading or writing the SQLite store
+    #[error("SQLite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+
+    /// A value read back from a store could not be parsed
+    #[error("Store holds an invalid value: {0}")]
+    StoreCorrupted(String),
+
     /// Indicates that an unsupported pool type was encountered
     #[error("Pool not supported")]
     UnsupportedPoolType,
@@ -28,4 +45,101 @@ pub enum PoolSyncError {
     /// Indicates that the chain was not set when it was required
     #[error("Chain not set")]
     ChainNotSet,
+
+    /// Indicates that no provider was supplied for a role and none could be read from the environment
+    #[error("No {role} provider set for {chain}, pass one to the builder or set the {env_var} environment variable")]
+    ProviderNotSet {
+        chain: Chain,
+        role: &'static str,
+        env_var: String,
+    },
+
+    /// Indicates that the same chain was configured more than once
+    #[error("Chain {0} configured more than once")]
+    DuplicateChain(Chain),
+
+    /// An rpc request kept failing while syncing a block range of a pool type
+    #[error("RPC error while syncing {pool_type} blocks {from_block}-{to_block}: {message}")]
+    RpcError {
+        pool_type: PoolType,
+        from_block: u64,
+        to_block: u64,
+        message: String,
+    },
+
+    /// A log could not be decoded or applied to the pool that emitted it
+    #[error("Failed to decode {pool_type} log from {address} in block {block}: {message}")]
+    DecodeError {
+        pool_type: PoolType,
+        address: Address,
+        block: u64,
+        message: String,
+    },
+
+    /// A cache file exists but its contents could not be read back
+    #[error("Corrupt {chain} {pool_type} cache at {path}: {message}")]
+    CacheCorrupted {
+        chain: Chain,
+        pool_type: PoolType,
+        path: String,
+        message: String,
+    },
+
+    /// The body of a cache file does not match the checksum in its header
+    ///
+    /// Only returned when the previous generation of the cache can not be read either.
+    #[error("Checksum mismatch in {chain} {pool_type} cache at {path}, expected {expected} but found {actual}")]
+    CacheChecksumMismatch {
+        chain: Chain,
+        pool_type: PoolType,
+        path: String,
+        expected: B256,
+        actual: B256,
+    },
+
+    /// A cache file was written by a newer version of the crate
+    #[error("Cache at {path} has format version {version}, this version of pool-sync reads up to {supported}")]
+    UnsupportedCacheVersion {
+        path: String,
+        version: u32,
+        supported: u32,
+    },
+
+    /// A binary cache file was written with an older layout of the pools, it has to be resynced
+    #[error("Binary cache at {path} has format version {version}, binary caches before version {oldest} have to be resynced")]
+    UnsupportedBinaryCache {
+        path: String,
+        version: u32,
+        oldest: u32,
+    },
+
+    /// Some pool types failed to sync while the rest were brought up to date
+    ///
+    /// The caches of the failed pool types keep their last good state and are retried
+    /// on the next sync. The report still holds all pools and the errors of the failed
+    /// pool types.
+    #[error("Partial sync, {} pool type(s) failed", report.failures().count())]
+    PartialSync { report: SyncReport },
+}
+
+impl PoolSyncError {
+    /// Wraps a provider failure with the pool type and block range it happened in
+    pub(crate) fn rpc(pool_type: PoolType, from_block: u64, to_block: u64, err: impl Display) -> Self {
+        PoolSyncError::RpcError {
+            pool_type,
+            from_block,
+            to_block,
+            message: err.to_string(),
+        }
+    }
+
+    /// Builds the error for a log that could not be applied to a pool
+    pub(crate) fn decode(pool_type: PoolType, log: &Log, err: impl Display) -> Self {
+        PoolSyncError::DecodeError {
+            pool_type,
+            address: log.address(),
+            block: log.block_number.unwrap_or_default(),
+            message: err.to_string(),
+        }
+    }
 }
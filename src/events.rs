@@ -18,6 +18,21 @@ sol!(
             uint256 amountIn,
             uint256 amountOut
         );
+        event PoolBalanceChanged(
+            bytes32 indexed poolId,
+            address indexed liquidityProvider,
+            address[] tokens,
+            int256[] deltas,
+            uint256[] protocolFeeAmounts
+        );
+        event PoolBalanceManaged(
+            bytes32 indexed poolId,
+            address indexed assetManager,
+            address indexed token,
+            int256 cashDelta,
+            int256 managedDelta
+        );
+        event SwapFeePercentageChanged(uint256 swapFeePercentage);
     }
 );
 
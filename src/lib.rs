@@ -7,7 +7,11 @@
 
 // Public re-exports
 pub use chain::Chain;
-pub use pool_sync::PoolSync;
+pub use endpoints::EndpointHealth;
+pub use live::{BlockUpdate, SyncHandle};
+pub use builder::ChainConfig;
+pub use cache::PoolCache;
+pub use pool_sync::{ChainSync, PoolSync, SyncProvider};
 pub use pools::pool_structures::{
     balancer_v2_structure::BalancerV2Pool,
     maverick_structure::MaverickPool,
@@ -17,16 +21,31 @@ pub use pools::pool_structures::{
     v3_structure::{TickInfo, UniswapV3Pool},
 };
 pub use pools::{Pool, PoolInfo, PoolType};
+pub use report::{ChainReport, PoolTypeReport, SyncReport};
 pub use rpc::Rpc;
+pub use store::{
+    CacheCompression, CacheEncoding, CacheHeader, FileStore, MemoryStore, PoolStore, SqliteStore,
+    BINARY_LAYOUT_VERSION, FORMAT_VERSION,
+};
+pub use tokens::{TokenInfo, TokenQuirk, TokenRegistry};
+pub use tokio_util::sync::CancellationToken;
 
 // Internal modules
 mod builder;
 mod cache;
 mod chain;
+mod endpoints;
 mod errors;
 mod events;
+mod limiter;
+mod live;
+mod multicall;
 mod pool_sync;
 mod pools;
+mod reorg;
+mod report;
 mod rpc;
+mod store;
+mod tokens;
 mod util;
 mod tests;
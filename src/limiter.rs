@@ -0,0 +1,116 @@
+//! RPC Rate Limiting
+//!
+//! This module provides the token bucket that paces every JSON-RPC request sent to an
+//! endpoint. The bucket is installed as a layer in the transport of the provider, so
+//! log queries, DataSync deploy calls, token metadata calls and block queries all draw
+//! from the same budget.
+
+use alloy::rpc::json_rpc::{RequestPacket, ResponsePacket};
+use alloy::transports::{BoxTransport, Transport, TransportError, TransportFut};
+use ratelimit::Ratelimiter;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::Service;
+
+/// Token bucket shared by all requests to a single endpoint
+///
+/// By default every request costs one token. Providers that bill in compute units can be
+/// matched by giving methods a weight, the bucket then refills in compute units per second.
+pub struct RpcLimiter {
+    bucket: Ratelimiter,
+    weights: HashMap<String, u64>,
+}
+
+impl RpcLimiter {
+    /// Creates a limiter that allows `per_second` tokens per second
+    pub fn new(per_second: u64, weights: HashMap<String, u64>) -> Self {
+        let per_second = per_second.max(1);
+        let (amount, interval) = refill(per_second);
+        let bucket = Ratelimiter::builder(amount, interval)
+            .max_tokens(per_second)
+            .initial_available(per_second)
+            .build()
+            .expect("refill amount and interval are non zero");
+        Self { bucket, weights }
+    }
+
+    /// Number of tokens the request costs
+    fn weight(&self, request: &RequestPacket) -> u64 {
+        let weight = |method: &str| self.weights.get(method).copied().unwrap_or(1);
+        match request {
+            RequestPacket::Single(request) => weight(request.method()),
+            RequestPacket::Batch(requests) => {
+                requests.iter().map(|request| weight(request.method())).sum()
+            }
+        }
+    }
+
+    /// Waits until the bucket holds enough tokens for the request and takes them
+    async fn acquire(&self, weight: u64) {
+        // heavier requests than the bucket can hold only wait for a full bucket
+        for _ in 0..weight.min(self.bucket.max_tokens()) {
+            while let Err(wait) = self.bucket.try_wait() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+}
+
+/// Refill amount and interval that add up to `per_second` tokens per second
+///
+/// The bucket is refilled in small steps so that requests are spread over the second.
+/// Above a token per millisecond the amount and interval are whole tokens and whole
+/// milliseconds, reduced by their common divisor so that no part of the rate is dropped.
+pub(crate) fn refill(per_second: u64) -> (u64, Duration) {
+    if per_second > 1000 {
+        let divisor = gcd(per_second, 1000);
+        (per_second / divisor, Duration::from_millis(1000 / divisor))
+    } else {
+        (1, Duration::from_nanos(1_000_000_000 / per_second))
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Transport that passes every request through a limiter before sending it
+#[derive(Clone)]
+pub struct RateLimitTransport {
+    inner: BoxTransport,
+    limiter: Arc<RpcLimiter>,
+}
+
+impl Service<RequestPacket> for RateLimitTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            limiter.acquire(limiter.weight(&request)).await;
+            inner.call(request).await
+        })
+    }
+}
+
+/// Wraps the transport so that all of its requests go through the limiter
+pub(crate) fn limit_transport(transport: BoxTransport, limiter: Arc<RpcLimiter>) -> BoxTransport {
+    RateLimitTransport {
+        inner: transport,
+        limiter,
+    }
+    .boxed()
+}
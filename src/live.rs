@@ -0,0 +1,253 @@
+//! Live Synchronization
+//!
+//! This module keeps a set of synced pools current after the initial sync. A background
+//! task per chain follows new blocks on its full node and applies their logs to the in
+//! memory pools using the same sync path as the initial sync.
+
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use futures::future::join_all;
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::cache::PoolCache;
+use crate::endpoints::pool_provider;
+use crate::errors::PoolSyncError;
+use crate::pools::{Pool, PoolType};
+use crate::rpc::RpcBudget;
+use crate::{Chain, ChainSync, PoolSync, TokenRegistry};
+
+/// Capacity of the update channel before slow subscribers start lagging
+const UPDATE_CHANNEL_SIZE: usize = 1024;
+
+/// Changes that were applied to a pool type for a range of new blocks
+#[derive(Debug, Clone)]
+pub struct BlockUpdate {
+    /// The chain the blocks belong to
+    pub chain: Chain,
+    /// The type of pools that were updated
+    pub pool_type: PoolType,
+    /// First block of the applied range, blocks from here on were replaced after a reorg
+    pub from_block: u64,
+    /// Last block of the applied range
+    pub to_block: u64,
+    /// Addresses of pools created in the range
+    pub new_pools: Vec<Address>,
+    /// Addresses of existing pools whose state changed in the range
+    pub updated_pools: Vec<Address>,
+}
+
+/// Shared state between the live sync task of a chain and its handle
+struct LiveState {
+    pool_caches: Vec<PoolCache>,
+    last_synced_block: u64,
+}
+
+/// Handle to a running live sync
+///
+/// Dropping the handle leaves the sync running in the background, call `stop` to
+/// shut it down and persist the caches.
+pub struct SyncHandle {
+    states: HashMap<Chain, Arc<RwLock<LiveState>>>,
+    updates: broadcast::Sender<BlockUpdate>,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+    pool_sync: PoolSync,
+}
+
+impl SyncHandle {
+    /// Returns a snapshot of all the pools that are being tracked on the chain
+    pub async fn pools(&self, chain: Chain) -> Vec<Pool> {
+        let Some(state) = self.states.get(&chain) else {
+            return Vec::new();
+        };
+        let state = state.read().await;
+        state
+            .pool_caches
+            .iter()
+            .flat_map(|cache| cache.pools.iter().cloned())
+            .collect()
+    }
+
+    /// Returns the last block that has been applied to the pools of the chain
+    pub async fn last_synced_block(&self, chain: Chain) -> Option<u64> {
+        let state = self.states.get(&chain)?;
+        Some(state.read().await.last_synced_block)
+    }
+
+    /// Returns the metadata of the tokens in the pools of every chain
+    pub fn tokens(&self) -> TokenRegistry {
+        self.pool_sync.tokens()
+    }
+
+    /// Subscribe to the updates produced for every new block on any chain
+    pub fn subscribe(&self) -> broadcast::Receiver<BlockUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Stops following the chains and writes the current pool state to the cache
+    pub async fn stop(self) -> Result<(), PoolSyncError> {
+        let _ = self.shutdown.send(true);
+        join_all(self.tasks).await;
+        for (chain, state) in &self.states {
+            let mut state = state.write().await;
+            self.pool_sync.chains[chain].write_caches(&mut state.pool_caches)?;
+        }
+        Ok(())
+    }
+}
+
+/// Spawns a task per chain that follows its head and returns a handle to them
+pub(crate) fn spawn(
+    pool_sync: PoolSync,
+    chain_caches: HashMap<Chain, (Vec<PoolCache>, u64)>,
+) -> SyncHandle {
+    let (updates, _) = broadcast::channel(UPDATE_CHANNEL_SIZE);
+    let (shutdown, shutdown_rx) = watch::channel(false);
+
+    let mut states = HashMap::new();
+    let mut tasks = Vec::new();
+    for (chain, (pool_caches, last_synced_block)) in chain_caches {
+        let state = Arc::new(RwLock::new(LiveState {
+            pool_caches,
+            last_synced_block,
+        }));
+        tasks.push(tokio::spawn(follow_chain(
+            pool_sync.chains[&chain].clone(),
+            pool_sync.rate_limit,
+            pool_sync.poll_interval,
+            state.clone(),
+            updates.clone(),
+            shutdown_rx.clone(),
+        )));
+        states.insert(chain, state);
+    }
+
+    SyncHandle {
+        states,
+        updates,
+        shutdown,
+        tasks,
+        pool_sync,
+    }
+}
+
+/// Polls for new blocks and applies them to the pools until shutdown is requested
+async fn follow_chain(
+    chain_sync: ChainSync,
+    rate_limit: u64,
+    poll_interval: Duration,
+    state: Arc<RwLock<LiveState>>,
+    updates: broadcast::Sender<BlockUpdate>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let archive = chain_sync.archive.clone();
+    let budget =
+        RpcBudget::new(rate_limit).with_log_endpoints(chain_sync.archive_endpoints.clone());
+    let mut interval = tokio::time::interval(poll_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => break,
+            _ = interval.tick() => {}
+        }
+
+        // the head, block hashes and reorg checks of a tick come from the same endpoint
+        let full = Arc::new(pool_provider(&chain_sync.full_endpoints.pinned()));
+        let head = match full.get_block_number().await {
+            Ok(head) => head,
+            Err(e) => {
+                error!("Failed to fetch the latest {} block number: {}", chain_sync.chain, e);
+                continue;
+            }
+        };
+
+        // sync copies of the caches that are behind, readers keep seeing the last applied
+        // block until the results are swapped in
+        let pending: Vec<PoolCache> = {
+            let state = state.read().await;
+            if head <= state.last_synced_block {
+                continue;
+            }
+            state
+                .pool_caches
+                .iter()
+                .filter(|cache| cache.last_synced_block < head)
+                .cloned()
+                .collect()
+        };
+
+        // pool types sync the new blocks concurrently, a pool type that fails keeps its last
+        // good state and retries the range on the next tick
+        let results = join_all(pending.into_iter().map(|mut cache| async {
+            let from_block = cache.last_synced_block + 1;
+            let result = chain_sync
+                .sync_cache(&mut cache, head, archive.clone(), full.clone(), budget.clone(), None)
+                .await;
+            (cache, from_block, result)
+        }))
+        .await;
+
+        let mut state = state.write().await;
+        for (cache, from_block, result) in results {
+            let pool_type = cache.pool_type;
+            match result {
+                Ok(synced) => {
+                    if let Some(live) = state
+                        .pool_caches
+                        .iter_mut()
+                        .find(|live| live.pool_type == pool_type)
+                    {
+                        *live = cache;
+                    }
+                    if !synced.pending_pools.is_empty() {
+                        warn!(
+                            "{} {} {} pools could not be built yet, retrying on the next block",
+                            synced.pending_pools.len(),
+                            chain_sync.chain,
+                            pool_type
+                        );
+                    }
+                    let new: HashSet<Address> = synced.new_pools.iter().copied().collect();
+                    let update = BlockUpdate {
+                        chain: chain_sync.chain,
+                        pool_type,
+                        from_block: synced.from_block,
+                        to_block: head,
+                        new_pools: synced.new_pools,
+                        updated_pools: synced
+                            .updated_pools
+                            .into_iter()
+                            .filter(|addr| !new.contains(addr))
+                            .collect(),
+                    };
+                    // no subscribers is not an error
+                    let _ = updates.send(update);
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to sync {} {} for blocks {}-{}: {}",
+                        chain_sync.chain, pool_type, from_block, head, e
+                    );
+                }
+            }
+        }
+
+        // only count a block as synced once every pool type has applied it
+        state.last_synced_block = state
+            .pool_caches
+            .iter()
+            .map(|cache| cache.last_synced_block)
+            .min()
+            .unwrap_or(head);
+        info!(
+            "Live sync of {} applied blocks up to {}",
+            chain_sync.chain, state.last_synced_block
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Multicall Batching
+//!
+//! This module packs many read calls into Multicall3 `aggregate3` calls. Contracts that
+//! are not deployed on chain, like the NameSync helper, can take part by placing their
+//! code at an unused address through a state override of the call.
+
+use alloy::eips::BlockId;
+use alloy::network::Network;
+use alloy::primitives::{address, Address, Bytes, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::state::StateOverride;
+use alloy::sol;
+use alloy::transports::Transport;
+use futures::future::try_join_all;
+use std::sync::Arc;
+
+use crate::errors::PoolSyncError;
+use crate::rpc::RpcBudget;
+
+/// Multicall3, deployed at the same address on every supported chain
+pub const MULTICALL3: Address = address!("cA11bde05977b3631167028862bE2a6793a3ca11");
+
+/// Calls packed into a single aggregate call
+const MAX_CALLS_PER_AGGREGATE: usize = 500;
+
+sol! {
+    #[sol(rpc)]
+    interface IMulticall3 {
+        struct Call3 {
+            address target;
+            bool allowFailure;
+            bytes callData;
+        }
+
+        struct CallResult {
+            bool success;
+            bytes returnData;
+        }
+
+        function aggregate3(Call3[] calldata calls) external payable returns (CallResult[] memory returnData);
+    }
+}
+
+/// Builds a call, a failing call without `allow_failure` fails its whole aggregate call
+pub(crate) fn call(target: Address, data: Vec<u8>, allow_failure: bool) -> IMulticall3::Call3 {
+    IMulticall3::Call3 {
+        target,
+        allowFailure: allow_failure,
+        callData: data.into(),
+    }
+}
+
+/// Runs the calls at the block, packed into as few aggregate calls as allowed
+///
+/// The aggregate calls are sent concurrently and every one of them acquires a permit from
+/// the budget. Returns the result of every call in order.
+pub(crate) async fn aggregate<P, T, N>(
+    provider: &Arc<P>,
+    calls: Vec<IMulticall3::Call3>,
+    block: BlockId,
+    overrides: StateOverride,
+    budget: &RpcBudget,
+) -> Result<Vec<IMulticall3::CallResult>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let multicall = IMulticall3::new(MULTICALL3, provider.clone());
+    let results = try_join_all(calls.chunks(MAX_CALLS_PER_AGGREGATE).map(|chunk| {
+        let (multicall, overrides) = (&multicall, &overrides);
+        async move {
+            let _permit = budget.acquire().await;
+            let mut call = multicall.aggregate3(chunk.to_vec()).block(block);
+            if !overrides.is_empty() {
+                call = call.state(overrides.clone());
+            }
+            call.call()
+                .await
+                .map_err(|e| PoolSyncError::ProviderError(e.to_string()))
+        }
+    }))
+    .await?;
+
+    Ok(results
+        .into_iter()
+        .flat_map(|result| result.returnData)
+        .collect())
+}
+
+/// Reads the 32 byte return value at the index
+pub(crate) fn word(data: &Bytes, index: usize) -> Result<U256, PoolSyncError> {
+    data.get(index * 32..(index + 1) * 32)
+        .map(U256::from_be_slice)
+        .ok_or_else(|| {
+            PoolSyncError::ProviderError(format!("Call returned {} bytes", data.len()))
+        })
+}
@@ -4,26 +4,82 @@
 //! blockchain networks and protocols. It includes the main `PoolSync` struct and its
 //! associated methods for configuring and executing the synchronization process.
 //!
-use alloy::providers::Provider;
-use alloy::providers::ProviderBuilder;
-use std::collections::HashMap;
-use std::sync::Arc;
+use alloy::network::{AnyNetwork, Network};
+use alloy::primitives::Address;
+use alloy::providers::{Provider, RootProvider};
+use alloy::transports::{BoxTransport, Transport};
+use futures::future::join_all;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
 use crate::builder::PoolSyncBuilder;
-use crate::cache::{read_cache_file, write_cache_file, PoolCache};
+use crate::cache::{PendingPool, PoolCache, SyncCheckpoint, SyncPhase};
 use crate::chain::Chain;
+use crate::endpoints::{pool_provider, EndpointHealth, EndpointPool};
 use crate::errors::*;
+use crate::live::{self, SyncHandle};
+use crate::report::{ChainReport, PoolTypeReport, SyncReport};
 use crate::pools::*;
-use crate::rpc::Rpc;
+use crate::reorg::REORG_WINDOW;
+use crate::rpc::{Rpc, RpcBudget, LIQUIDITY_BATCH_SIZE};
+use crate::store::PoolStore;
+use crate::tokens::{self, TokenRegistry};
 
-/// The main struct for pool synchronization
-pub struct PoolSync {
-    /// Map of pool types to their fetcher implementations
-    pub fetchers: HashMap<PoolType, Arc<dyn PoolFetcher>>,
+/// Provider type used for both the archive and the full node
+///
+/// The transport is boxed so that http, websocket and ipc providers can be used interchangeably
+pub type SyncProvider = RootProvider<BoxTransport, AnyNetwork>;
+
+/// Result of syncing a single cache up to a new block
+pub(crate) struct CacheSync {
+    /// First block that was applied, earlier than requested if a reorg was rolled back
+    pub from_block: u64,
+    /// Last block that was applied, earlier than requested when resuming a checkpoint
+    pub to_block: u64,
+    /// Addresses of the pools added to the cache, including pending pools that were built
+    pub new_pools: Vec<Address>,
+    /// Addresses of all pools whose state was updated
+    pub updated_pools: HashSet<Address>,
+    /// Addresses of the pools that could not be built and are retried on the next sync
+    pub pending_pools: Vec<Address>,
+}
+
+/// Pools, providers and sync state of a single chain
+#[derive(Clone)]
+pub struct ChainSync {
     /// The chain to sync on
     pub chain: Chain,
-    /// The rate limit of the rpc
+    /// Map of pool types to their fetcher implementations
+    pub fetchers: HashMap<PoolType, Arc<dyn PoolFetcher>>,
+    /// Block to start syncing each pool type from when it has no cache yet
+    pub start_blocks: HashMap<PoolType, u64>,
+    /// Archive node used for historical log queries
+    pub(crate) archive: Arc<SyncProvider>,
+    /// Endpoints behind the archive provider
+    pub(crate) archive_endpoints: EndpointPool,
+    /// Full node endpoints used for contract calls and the chain head, pinned per sync pass
+    pub(crate) full_endpoints: EndpointPool,
+    /// Metadata of the tokens in the pools, shared by all chains
+    pub(crate) tokens: Arc<Mutex<TokenRegistry>>,
+    /// Where the pools and sync state are persisted, shared by all chains
+    pub(crate) store: Arc<dyn PoolStore>,
+}
+
+/// The main struct for pool synchronization
+#[derive(Clone)]
+pub struct PoolSync {
+    /// The chains to sync, each with its own pool types and providers
+    pub chains: HashMap<Chain, ChainSync>,
+    /// The rate limit of the rpc, bounds the requests in flight on each chain
     pub rate_limit: u64,
+    /// How often the live sync checks for a new block
+    pub poll_interval: Duration,
+    /// Metadata of the tokens in the pools of every chain
+    pub(crate) tokens: Arc<Mutex<TokenRegistry>>,
 }
 
 impl PoolSync {
@@ -32,129 +88,587 @@ impl PoolSync {
         PoolSyncBuilder::default()
     }
 
-    /// Synchronizes all added pools for the specified chain
-    pub async fn sync_pools(&self) -> Result<(Vec<Pool>, u64), PoolSyncError> {
-        // load in the dotenv
-        dotenv::dotenv().ok();
-
-        // setup arvhice node provider
-        let archive = Arc::new(
-            ProviderBuilder::new()
-                .network::<alloy::network::AnyNetwork>()
-                .on_http(std::env::var("ARCHIVE").unwrap().parse().unwrap()),
-        );
-
-        // setup full node provider
-        let full = Arc::new(
-            ProviderBuilder::new()
-                .network::<alloy::network::AnyNetwork>()
-                .on_http(std::env::var("FULL").unwrap().parse().unwrap()),
-        );
-
-        // create the cache files
-        std::fs::create_dir_all("cache").unwrap();
-
-        // create all of the caches
-        let mut pool_caches: Vec<PoolCache> = self
-            .fetchers
+    /// Returns the metadata of the tokens in the pools of every chain
+    pub fn tokens(&self) -> TokenRegistry {
+        self.tokens.lock().unwrap().clone()
+    }
+
+    /// Synchronizes all added pools on every configured chain
+    ///
+    /// The chains are synced concurrently. The report holds the pools of every chain
+    /// along with what the sync did for each of its pool types.
+    pub async fn sync_pools(&self) -> Result<SyncReport, PoolSyncError> {
+        self.sync_pools_until(CancellationToken::new()).await
+    }
+
+    /// Synchronizes all added pools until done or until the token is cancelled
+    ///
+    /// Long syncs are checkpointed to the cache after every phase. Cancelling the token
+    /// stops the sync at the next phase, writes the checkpoint and returns `Cancelled`.
+    /// The next sync resumes from the checkpoint.
+    pub async fn sync_pools_until(
+        &self,
+        cancel: CancellationToken,
+    ) -> Result<SyncReport, PoolSyncError> {
+        let chain_syncs = join_all(self.chains.values().map(|chain_sync| {
+            let cancel = cancel.clone();
+            async move {
+                // create all of the caches and bring them up to the chain head
+                let mut pool_caches = chain_sync.load_caches()?;
+                let result = chain_sync
+                    .catch_up(&mut pool_caches, self.rate_limit, &cancel)
+                    .await;
+
+                // write all of the cache files, failed pool types keep their last good state
+                // and cancelled ones their checkpoint
+                chain_sync.write_caches(&mut pool_caches)?;
+                let pool_types = result?;
+                let pools = pool_caches
+                    .into_iter()
+                    .flat_map(|cache| cache.pools)
+                    .collect();
+                Ok::<_, PoolSyncError>((chain_sync.chain, ChainReport { pools, pool_types }))
+            }
+        }))
+        .await;
+
+        let report = SyncReport {
+            chains: chain_syncs.into_iter().collect::<Result<_, _>>()?,
+            tokens: self.tokens(),
+        };
+        if report.failures().next().is_none() {
+            Ok(report)
+        } else {
+            Err(PoolSyncError::PartialSync { report })
+        }
+    }
+
+    /// Synchronizes all added pools and then keeps following the chain heads
+    ///
+    /// The initial sync runs to completion before this returns. Afterwards a background
+    /// task per chain polls the full node for new blocks and applies their logs to the
+    /// in memory pools, picking up newly created pools along the way. The returned handle
+    /// gives access to the live pool state and to a stream of per block updates.
+    pub async fn run_live(&self) -> Result<SyncHandle, PoolSyncError> {
+        let chain_syncs = join_all(self.chains.values().map(|chain_sync| async move {
+            let mut pool_caches = chain_sync.load_caches()?;
+            let pool_types = chain_sync
+                .catch_up(&mut pool_caches, self.rate_limit, &CancellationToken::new())
+                .await?;
+            chain_sync.write_caches(&mut pool_caches)?;
+
+            // the live task retries the failed pool types on every new block
+            for (pool_type, report) in pool_types {
+                if let Some(e) = report.error {
+                    warn!("{} {} failed the initial sync: {}", chain_sync.chain, pool_type, e);
+                }
+            }
+            let last_synced_block = pool_caches
+                .iter()
+                .map(|cache| cache.last_synced_block)
+                .min()
+                .unwrap_or_default();
+            Ok::<_, PoolSyncError>((chain_sync.chain, (pool_caches, last_synced_block)))
+        }))
+        .await;
+
+        let chain_caches = chain_syncs.into_iter().collect::<Result<_, _>>()?;
+        Ok(live::spawn(self.clone(), chain_caches))
+    }
+}
+
+impl ChainSync {
+    /// Returns the health of the archive endpoints in the order they were configured
+    pub fn archive_health(&self) -> Vec<EndpointHealth> {
+        self.archive_endpoints.health()
+    }
+
+    /// Returns the health of the full node endpoints in the order they were configured
+    pub fn full_health(&self) -> Vec<EndpointHealth> {
+        self.full_endpoints.health()
+    }
+
+    /// Reads the cache for every pool type that is being synced
+    pub(crate) fn load_caches(&self) -> Result<Vec<PoolCache>, PoolSyncError> {
+        let tokens = self.store.load_tokens(self.chain)?;
+        self.tokens.lock().unwrap().load(self.chain, tokens);
+
+        self.fetchers
             .keys()
-            .map(|pool_type| read_cache_file(pool_type, self.chain).unwrap())
-            .collect();
+            .map(|pool_type| {
+                let cache = self.store.load(self.chain, *pool_type)?;
+                Ok(cache.unwrap_or_else(|| PoolCache::new(*pool_type, self.start_blocks[pool_type])))
+            })
+            .collect()
+    }
+
+    /// Writes the cache for every pool type that is being synced
+    pub(crate) fn write_caches(&self, pool_caches: &mut [PoolCache]) -> Result<(), PoolSyncError> {
+        {
+            let registry = self.tokens.lock().unwrap();
+            let mut tokens: Vec<_> = registry.chain_tokens(self.chain).collect();
+            tokens.sort_by_key(|info| info.address);
+            self.store.store_tokens(self.chain, &tokens)?;
+        }
+        pool_caches
+            .iter_mut()
+            .try_for_each(|cache| self.write_cache(cache))
+    }
 
+    /// Writes the cache to the store, its pools count as unchanged afterwards
+    pub(crate) fn write_cache(&self, cache: &mut PoolCache) -> Result<(), PoolSyncError> {
+        self.store.upsert(self.chain, cache)?;
+        cache.dirty.clear();
+        cache.removed.clear();
+        Ok(())
+    }
+
+    /// Syncs all of the caches until they are caught up with the tip of the chain
+    ///
+    /// The pool types are synced concurrently and share a single rpc budget. A pool type
+    /// that fails to sync is left at its last good state and skipped for the rest of the
+    /// catch up, its error is stored in its report.
+    ///
+    /// Pool types that are far behind first sync up to the blocks that can no longer be
+    /// reorged with checkpoints, the remaining blocks are synced in one go afterwards.
+    async fn catch_up(
+        &self,
+        pool_caches: &mut [PoolCache],
+        rate_limit: u64,
+        cancel: &CancellationToken,
+    ) -> Result<HashMap<PoolType, PoolTypeReport>, PoolSyncError> {
+        let archive = self.archive.clone();
+        let budget =
+            RpcBudget::new(rate_limit).with_log_endpoints(self.archive_endpoints.clone());
+        let mut reports: HashMap<PoolType, PoolTypeReport> = pool_caches
+            .iter_mut()
+            .map(|cache| {
+                let report = PoolTypeReport {
+                    recovered_from: cache.recovered_from.take(),
+                    ..Default::default()
+                };
+                (cache.pool_type, report)
+            })
+            .collect();
         let mut fully_synced = false;
-        let mut last_synced_block = 0;
 
         while !fully_synced {
-            fully_synced = true;
-            let end_block = full.get_block_number().await.unwrap();
+            // the head, block hashes and reorg checks of a pass come from the same endpoint
+            let full = Arc::new(pool_provider(&self.full_endpoints.pinned()));
+            let end_block = full
+                .get_block_number()
+                .await
+                .map_err(|e| PoolSyncError::ProviderError(e.to_string()))?;
 
-            for cache in &mut pool_caches {
-                let start_block = cache.last_synced_block + 1;
-                if start_block <= end_block {
-                    fully_synced = false;
+            // every pool type that is behind syncs at the same time
+            let pending = pool_caches.iter_mut().filter(|cache| {
+                cache.last_synced_block < end_block && reports[&cache.pool_type].error.is_none()
+            });
+            let results = join_all(pending.map(|cache| async {
+                let pool_type = cache.pool_type;
+                let budget = budget.scoped();
+                let started = Instant::now();
+                // blocks that can no longer be reorged are synced with checkpoints
+                let (target, cancel) = if cache.checkpoint.is_some()
+                    || cache.last_synced_block + REORG_WINDOW < end_block
+                {
+                    (end_block.saturating_sub(REORG_WINDOW), Some(cancel))
+                } else {
+                    (end_block, None)
+                };
+                let result = self
+                    .sync_cache(cache, target, archive.clone(), full.clone(), budget.clone(), cancel)
+                    .await;
+                (pool_type, result, budget.calls(), started.elapsed())
+            }))
+            .await;
 
-                    let fetcher = self.fetchers[&cache.pool_type].clone();
-
-                    // fetch all of the pool addresses
-                    let pool_addrs = Rpc::fetch_pool_addrs(
-                        start_block,
-                        end_block,
-                        archive.clone(),
-                        fetcher.clone(),
-                        self.chain,
-                        self.rate_limit,
-                    )
-                    .await
-                    .expect(
-                        "Failed to fetch pool addresses. Exiting due to having inconclusive state",
-                    );
+            fully_synced = results.is_empty();
+            for (pool_type, result, rpc_calls, elapsed) in results {
+                let report = reports.get_mut(&pool_type).unwrap();
+                report.rpc_calls += rpc_calls;
+                report.elapsed += elapsed;
+                match result {
+                    Ok(synced) => {
+                        info!(
+                            "{} {} synced to block {}, {} new pools",
+                            self.chain,
+                            pool_type,
+                            synced.to_block,
+                            synced.new_pools.len()
+                        );
+                        let from_block = match &report.synced_blocks {
+                            Some(range) => (*range.start()).min(synced.from_block),
+                            None => synced.from_block,
+                        };
+                        report.synced_blocks = Some(from_block..=synced.to_block);
+                        report.new_pools += synced.new_pools.len();
+                        report.updated_pools += synced.updated_pools.len();
+                    }
+                    Err(e) => {
+                        warn!("{} {} failed to sync: {}", self.chain, pool_type, e);
+                        report.error = Some(e);
+                    }
+                }
+            }
+            if cancel.is_cancelled() {
+                return Err(PoolSyncError::Cancelled);
+            }
+        }
 
-                    // populate all of the pool data
-                    let mut new_pools = Rpc::populate_pools(
-                        pool_addrs,
-                        full.clone(),
-                        cache.pool_type,
-                        fetcher.clone(),
-                        self.rate_limit,
-                        self.chain,
-                    )
-                    .await
-                    .expect("Failed to sync pool data, Exiting due to haveing inconclusive state");
+        for cache in pool_caches.iter() {
+            let report = reports.get_mut(&cache.pool_type).unwrap();
+            report.last_synced_block = cache.last_synced_block;
+            report.pending_pools = cache.pending_pools.iter().map(|pending| pending.address).collect();
+        }
+        Ok(reports)
+    }
 
+    /// Syncs a single cache from its last synced block up to the end block
+    ///
+    /// If the chain reorganized since the last sync, the cache is first rolled back to the
+    /// common ancestor. The pools created in the range are then discovered and built, and
+    /// the logs of the range are applied to both the old and the new pools. Pools that
+    /// could not be built are kept as pending and retried on the next call.
+    ///
+    /// With a cancellation token, the progress is checkpointed to the cache file after
+    /// every phase and the sync stops at the next phase once the token is cancelled. An
+    /// interrupted sync is resumed from its checkpoint, ignoring the requested end block.
+    ///
+    /// The cache is only modified by a successful sync or by a written checkpoint, on error
+    /// it keeps the state of the last sync or checkpoint.
+    pub(crate) async fn sync_cache<P, T, N>(
+        &self,
+        cache: &mut PoolCache,
+        end_block: u64,
+        archive: Arc<P>,
+        full: Arc<P>,
+        budget: RpcBudget,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<CacheSync, PoolSyncError>
+    where
+        P: Provider<T, N> + Sync + 'static,
+        T: Transport + Sync + Clone + 'static,
+        N: Network,
+    {
+        // everything is applied to a copy that only replaces the cache once nothing can
+        // fail anymore, a failed sync leaves the cache at its last good state
+        let mut staged = cache.clone();
+        let fetcher = self.fetchers[&staged.pool_type].clone();
 
-                    // catch up all the old pools
-                    Rpc::populate_liquidity(
-                        start_block,
-                        end_block,
-                        &mut cache.pools,
-                        archive.clone(),
-                        cache.pool_type,
-                        self.rate_limit,
-                        cache.is_initial_sync,
-                    )
-                    .await
-                    .expect("Failed to populate liquidity information, Exiting due to having inconclusive state");
+        let (end_block, mut phase) = match staged.checkpoint.take() {
+            Some(checkpoint) => {
+                info!(
+                    "{} {} resuming sync to block {} from checkpoint",
+                    self.chain, staged.pool_type, checkpoint.end_block
+                );
+                (checkpoint.end_block, checkpoint.phase)
+            }
+            None => {
+                // make sure we are building on top of the canonical chain
+                if let Some(ancestor) = Rpc::find_reorg_ancestor(&staged.block_window, full.clone(), &budget).await? {
+                    warn!(
+                        "{} {} reorg detected, rolling back from block {} to {}",
+                        self.chain, staged.pool_type, staged.last_synced_block, ancestor
+                    );
+                    let touched = staged.journal.rollback(&mut staged.pools, ancestor);
+                    staged.mark_changed(touched);
+                    staged.block_window.truncate(ancestor);
+                    staged.pending_pools.retain(|pending| pending.since_block <= ancestor);
+                    staged.last_synced_block = ancestor;
+                }
+                (end_block, SyncPhase::Started)
+            }
+        };
+        let start_block = staged.last_synced_block + 1;
+        let mut updated = HashSet::new();
 
-                    // update the new pools
-                    if !new_pools.is_empty() {
-                        Rpc::populate_liquidity(
+        let (new_pools, snapshot_pools, retried_pools, failed, still_pending) = loop {
+            phase = match phase {
+                // fetch all of the pool addresses
+                SyncPhase::Started => {
+                    let pool_addrs = until_cancelled(
+                        cancel,
+                        Rpc::fetch_pool_addrs(
                             start_block,
                             end_block,
-                            &mut new_pools,
                             archive.clone(),
-                            cache.pool_type,
-                            self.rate_limit,
-                            true,
+                            fetcher.clone(),
+                            self.chain,
+                            budget.clone(),
+                        ),
+                    )
+                    .await?;
+                    SyncPhase::Discovered { pool_addrs }
+                }
+
+                // populate all of the pool data
+                SyncPhase::Discovered { pool_addrs } => {
+                    let (mut new_pools, failed) = until_cancelled(
+                        cancel,
+                        Rpc::populate_pools(
+                            pool_addrs,
+                            full.clone(),
+                            staged.pool_type,
+                            fetcher.clone(),
+                            budget.clone(),
+                            self.chain,
+                        ),
+                    )
+                    .await?;
+
+                    // retry the pools that could not be built during earlier syncs
+                    let (mut retried_pools, still_pending) = if staged.pending_pools.is_empty() {
+                        (Vec::new(), Vec::new())
+                    } else {
+                        until_cancelled(
+                            cancel,
+                            Rpc::populate_pools(
+                                staged.pending_pools.iter().map(|pending| pending.address).collect(),
+                                full.clone(),
+                                staged.pool_type,
+                                fetcher.clone(),
+                                budget.clone(),
+                                self.chain,
+                            ),
                         )
-                        .await
-                        .expect("Failed to populate liquidity information, Exiting due to having inconclusive state");
+                        .await?
+                    };
+
+                    // token metadata is fetched once per token and shared by all pools, the
+                    // pools are still synced without it
+                    let registered = until_cancelled(
+                        cancel,
+                        tokens::register_pools(
+                            &full,
+                            new_pools.iter_mut().chain(retried_pools.iter_mut()),
+                            self.chain,
+                            &self.tokens,
+                            &budget,
+                        ),
+                    )
+                    .await;
+                    match registered {
+                        Err(PoolSyncError::Cancelled) => return Err(PoolSyncError::Cancelled),
+                        Err(e) => warn!(
+                            "{} {} failed to fetch token metadata: {}",
+                            self.chain, staged.pool_type, e
+                        ),
+                        Ok(()) => {}
+                    }
+
+                    // bootstrap the ticks of new v3 pools from their storage at the end block
+                    // instead of replaying their logs, pools whose snapshot failed replay them
+                    let (snapshot_pools, new_pools, mut retried_pools) = if staged.pool_type.is_v3() {
+                        let snapshot = async {
+                            let (mut snapshot_pools, new_pools) = Rpc::snapshot_v3_pools(
+                                new_pools,
+                                end_block,
+                                archive.clone(),
+                                staged.pool_type,
+                                budget.clone(),
+                            )
+                            .await;
+                            let (snapshot_retried, retried_pools) = Rpc::snapshot_v3_pools(
+                                retried_pools,
+                                end_block,
+                                archive.clone(),
+                                staged.pool_type,
+                                budget.clone(),
+                            )
+                            .await;
+                            snapshot_pools.extend(snapshot_retried);
+                            Ok((snapshot_pools, new_pools, retried_pools))
+                        };
+                        until_cancelled(cancel, snapshot).await?
+                    } else {
+                        (Vec::new(), new_pools, retried_pools)
+                    };
+
+                    // the retried pools were created before this range, replay them up to it
+                    let since_block = staged
+                        .pending_pools
+                        .iter()
+                        .map(|pending| pending.since_block)
+                        .min()
+                        .unwrap_or(start_block);
+                    if !retried_pools.is_empty() && since_block < start_block {
+                        updated.extend(
+                            until_cancelled(
+                                cancel,
+                                Rpc::populate_liquidity(
+                                    since_block,
+                                    start_block - 1,
+                                    &mut retried_pools,
+                                    archive.clone(),
+                                    staged.pool_type,
+                                    budget.clone(),
+                                    true,
+                                    Some(&mut staged.journal),
+                                ),
+                            )
+                            .await?,
+                        );
                     }
 
+                    SyncPhase::Populating {
+                        new_pools,
+                        snapshot_pools,
+                        retried_pools,
+                        failed,
+                        still_pending,
+                        next_block: start_block,
+                    }
+                }
 
-                    // merge old and new
-                    cache.pools.extend(new_pools);
+                // every pool is caught up with the end block
+                SyncPhase::Populating {
+                    new_pools,
+                    snapshot_pools,
+                    retried_pools,
+                    failed,
+                    still_pending,
+                    next_block,
+                } if next_block > end_block => {
+                    break (new_pools, snapshot_pools, retried_pools, failed, still_pending)
+                }
 
+                // apply the logs of the next batch, the old pools go last so that a failed
+                // batch leaves the cache untouched. A batch is never interrupted, the sync
+                // stops between batches once the token is cancelled
+                SyncPhase::Populating {
+                    mut new_pools,
+                    snapshot_pools,
+                    mut retried_pools,
+                    failed,
+                    still_pending,
+                    next_block,
+                } => {
+                    if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+                        return Err(PoolSyncError::Cancelled);
+                    }
+                    let batch_end = (next_block + LIQUIDITY_BATCH_SIZE).min(end_block);
+                    let batches = [
+                        (&mut new_pools, true, None),
+                        (&mut retried_pools, true, Some(&mut staged.journal)),
+                    ];
+                    for (pools, is_initial_sync, journal) in batches {
+                        if !pools.is_empty() {
+                            updated.extend(
+                                Rpc::populate_liquidity(
+                                    next_block,
+                                    batch_end,
+                                    pools,
+                                    archive.clone(),
+                                    staged.pool_type,
+                                    budget.clone(),
+                                    is_initial_sync,
+                                    journal,
+                                )
+                                .await?,
+                            );
+                        }
+                    }
+                    let changed = Rpc::populate_liquidity(
+                        next_block,
+                        batch_end,
+                        &mut staged.pools,
+                        archive.clone(),
+                        staged.pool_type,
+                        budget.clone(),
+                        staged.is_initial_sync,
+                        Some(&mut staged.journal),
+                    )
+                    .await?;
+                    staged.mark_changed(changed.iter().copied());
+                    updated.extend(changed);
 
-                    // update info for cache
-                    cache.last_synced_block = end_block;
-                    last_synced_block = end_block;
-                    cache.is_initial_sync = false;
+                    SyncPhase::Populating {
+                        new_pools,
+                        snapshot_pools,
+                        retried_pools,
+                        failed,
+                        still_pending,
+                        next_block: batch_end + 1,
+                    }
                 }
+            };
+
+            // persist the progress so that an interrupted sync can resume from here
+            if cancel.is_some() {
+                staged.checkpoint = Some(SyncCheckpoint {
+                    end_block,
+                    phase: phase.clone(),
+                });
+                self.write_cache(&mut staged)?;
+                *cache = staged.clone();
+            }
+        };
+        staged.checkpoint = None;
+        let end_hash = Rpc::get_block_hash(end_block, full.clone(), &budget).await?;
+
+        // merge old and new, new pools are dropped again if their block is reorged out
+        let mut new_addrs: Vec<Address> = new_pools.iter().map(|pool| pool.address()).collect();
+        for address in &new_addrs {
+            staged.journal.record_created(end_block, *address);
+        }
+        for pool in &snapshot_pools {
+            let address = pool.address();
+            if !staged.pending_pools.iter().any(|pending| pending.address == address) {
+                staged.journal.record_created(end_block, address);
             }
+            updated.insert(address);
+            new_addrs.push(address);
         }
+        new_addrs.extend(retried_pools.iter().map(|pool| pool.address()));
+        staged.pools.extend(new_pools);
+        staged.pools.extend(snapshot_pools);
+        staged.pools.extend(retried_pools);
+        staged.mark_changed(new_addrs.iter().copied());
 
-        // write all of the cache files
-        pool_caches
-            .iter()
-            .for_each(|cache| write_cache_file(cache, self.chain).unwrap());
-
-        // return all the pools
-        Ok((
-            pool_caches
-                .into_iter()
-                .flat_map(|cache| cache.pools)
-                .collect(),
-            last_synced_block,
-        ))
+        // keep every pool that could not be built for the next sync
+        staged
+            .pending_pools
+            .retain(|pending| still_pending.contains(&pending.address));
+        staged.pending_pools.extend(failed.into_iter().map(|address| PendingPool {
+            address,
+            since_block: start_block,
+        }));
+
+        // update info for cache
+        staged.block_window.record(end_block, end_hash);
+        staged.journal.prune(end_block);
+        staged.last_synced_block = end_block;
+        staged.is_initial_sync = false;
+        if self.store.incremental() {
+            self.write_cache(&mut staged)?;
+        }
+        let pending_pools = staged.pending_pools.iter().map(|pending| pending.address).collect();
+        *cache = staged;
+
+        Ok(CacheSync {
+            from_block: start_block,
+            to_block: end_block,
+            new_pools: new_addrs,
+            updated_pools: updated,
+            pending_pools,
+        })
+    }
+}
+
+/// Runs the future unless the token is cancelled first
+async fn until_cancelled<F, R>(
+    cancel: Option<&CancellationToken>,
+    future: F,
+) -> Result<R, PoolSyncError>
+where
+    F: Future<Output = Result<R, PoolSyncError>>,
+{
+    match cancel {
+        Some(cancel) => tokio::select! {
+            result = future => result,
+            _ = cancel.cancelled() => Err(PoolSyncError::Cancelled),
+        },
+        None => future.await,
     }
 }
@@ -108,6 +108,8 @@ sol!(
 
 // Curve
 sol!(
+    // the generated create function takes every pool parameter as an argument
+    #[allow(clippy::too_many_arguments)]
     #[derive(Debug)]
     #[sol(rpc)]
     TwoCryptoFactory,
@@ -115,6 +117,8 @@ sol!(
 );
 
 sol!(
+    // the generated create function takes every pool parameter as an argument
+    #[allow(clippy::too_many_arguments)]
     #[derive(Debug)]
     #[sol(rpc)]
     TriCryptoFactory,
@@ -122,6 +126,8 @@ sol!(
 );
 
 sol!(
+    // the generated create function takes every pool parameter as an argument
+    #[allow(clippy::too_many_arguments)]
     #[derive(Debug)]
     #[sol(rpc)]
     BalancerV2Factory,
@@ -207,6 +213,20 @@ sol!(
     "src/abi/TriCurveDataSync.json"
 );
 
+sol!(
+    #[derive(Debug)]
+    #[sol(rpc)]
+    NameSync,
+    "src/abi/NameSync.json"
+);
+
+sol!(
+    #[derive(Debug)]
+    #[sol(rpc)]
+    UniswapV3TickSync,
+    "src/abi/UniswapV3TickSync.json"
+);
+
 sol!(
     #[derive(Debug)]
     #[sol(rpc)]
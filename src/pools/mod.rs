@@ -19,11 +19,13 @@ use std::fmt;
 
 use crate::chain::Chain;
 use crate::impl_pool_info;
+use crate::tokens::{TokenInfo, TokenRegistry};
 
-mod gen;
+pub(crate) mod gen;
 pub mod pool_builder;
 pub mod pool_fetchers;
 pub mod pool_structures;
+pub mod snapshot;
 
 /// Enumerates the supported pool types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -211,60 +213,51 @@ impl Pool {
     }
 
     pub fn is_v2(&self) -> bool {
-        match self {
-            Pool::UniswapV2(_) => true,
-            Pool::SushiSwapV2(_) => true,
-            Pool::PancakeSwapV2(_) => true,
-            Pool::Aerodrome(_) => true,
-            Pool::BaseSwapV2(_) => true,
-            Pool::AlienBaseV2(_) => true,
-            Pool::SwapBasedV2(_) => true,
-            Pool::DackieSwapV2(_) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Pool::UniswapV2(_)
+                | Pool::SushiSwapV2(_)
+                | Pool::PancakeSwapV2(_)
+                | Pool::Aerodrome(_)
+                | Pool::BaseSwapV2(_)
+                | Pool::AlienBaseV2(_)
+                | Pool::SwapBasedV2(_)
+                | Pool::DackieSwapV2(_)
+        )
     }
 
     pub fn is_v3(&self) -> bool {
-        match self {
-            Pool::UniswapV3(_) => true,
-            Pool::SushiSwapV3(_) => true,
-            Pool::PancakeSwapV3(_) => true,
-            Pool::Slipstream(_) => true,
-            Pool::BaseSwapV3(_) => true,
-            Pool::AlienBaseV3(_) => true,
-            Pool::SwapBasedV3(_) => true,
-            Pool::DackieSwapV3(_) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Pool::UniswapV3(_)
+                | Pool::SushiSwapV3(_)
+                | Pool::PancakeSwapV3(_)
+                | Pool::Slipstream(_)
+                | Pool::BaseSwapV3(_)
+                | Pool::AlienBaseV3(_)
+                | Pool::SwapBasedV3(_)
+                | Pool::DackieSwapV3(_)
+        )
     }
 
     pub fn is_maverick(&self) -> bool {
-        match self {
-            Pool::MaverickV1(_) => true,
-            Pool::MaverickV2(_) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Pool::MaverickV1(_)
+                | Pool::MaverickV2(_)
+        )
     }
 
     pub fn is_curve_two(&self) -> bool {
-        match self {
-            Pool::CurveTwoCrypto(_) => true,
-            _ => false,
-        }
+        matches!(self, Pool::CurveTwoCrypto(_))
     }
 
     pub fn is_curve_tri(&self) -> bool {
-        match self {
-            Pool::CurveTriCrypto(_) => true,
-            _ => false,
-        }
+        matches!(self, Pool::CurveTriCrypto(_))
     }
 
     pub fn is_balancer(&self) -> bool {
-        match self {
-            Pool::BalancerV2(_) => true,
-            _ => false,
-        }
+        matches!(self, Pool::BalancerV2(_))
     }
 
     pub fn get_v2(&self) -> Option<&UniswapV2Pool> {
@@ -381,13 +374,24 @@ impl Pool {
         }
     }
 
+    /// Addresses of every token in the pool
+    pub fn token_addresses(&self) -> Vec<Address> {
+        let mut tokens = vec![self.token0_address(), self.token1_address()];
+        if let Some(pool) = self.get_balancer() {
+            tokens.extend(&pool.additional_tokens);
+        } else if let Some(pool) = self.get_curve_tri() {
+            tokens.push(pool.token2);
+        }
+        tokens
+    }
+
     pub fn is_valid(&self) -> bool {
         self.address() != Address::ZERO
             && self.token0_address() != Address::ZERO
             && self.token1_address() != Address::ZERO
     }
 
-    fn update_token0_name(pool: &mut Pool, token0: String) {
+    pub fn update_token0_name(pool: &mut Pool, token0: String) {
         if pool.is_v2() {
             let pool = pool.get_v2_mut().unwrap();
             pool.token0_name = token0;
@@ -475,6 +479,12 @@ pub trait PoolFetcher: Send + Sync {
     /// Returns the factory address for the given chain
     fn factory_address(&self, chain: Chain) -> Address;
 
+    /// Returns the block the factory was deployed at on the given chain
+    ///
+    /// A fresh sync starts discovering pools from here, so it must never be later than
+    /// the actual deployment. An earlier block only costs a few empty log queries.
+    fn deployment_block(&self, chain: Chain) -> u64;
+
     /// Returns the event signature for pool creation
     fn pair_created_signature(&self) -> &str;
 
@@ -497,6 +507,16 @@ pub trait PoolInfo {
     fn pool_type(&self) -> PoolType;
     fn fee(&self) -> u32;
     fn stable(&self) -> bool;
+
+    /// Metadata of token0 on the chain, resolved through the registry
+    fn token0_info<'a>(&self, registry: &'a TokenRegistry, chain: Chain) -> Option<&'a TokenInfo> {
+        registry.get(chain, self.token0_address())
+    }
+
+    /// Metadata of token1 on the chain, resolved through the registry
+    fn token1_info<'a>(&self, registry: &'a TokenRegistry, chain: Chain) -> Option<&'a TokenInfo> {
+        registry.get(chain, self.token1_address())
+    }
 }
 
 /* 
@@ -1,13 +1,10 @@
-//use crate::{
-//    pools::{Pool, PoolType}, rpc::{DataEvents, PancakeSwap, Rpc}
-//}; //, snapshot::{v3_tick_snapshot, v3_tickbitmap_snapshot}};
-use crate::PoolInfo;
 use alloy::dyn_abi::DynSolType;
 use alloy::network::Network;
 use alloy::primitives::{address, Address};
 use alloy::providers::Provider;
 use alloy::transports::Transport;
 use anyhow::Result;
+use log::info;
 use rand::Rng;
 use std::sync::Arc;
 use std::time::Duration;
@@ -17,13 +14,17 @@ use super::gen::{
     V2DataSync, V3DataSync,
 };
 
-use crate::pools::gen::ERC20;
 use crate::pools::gen::{AerodromePool, AerodromeV2Factory};
-use crate::pools::{Pool, PoolType, Chain};
+use crate::pools::{Pool, PoolInfo, PoolType, Chain};
+use crate::rpc::RpcBudget;
 
 pub const INITIAL_BACKOFF: u64 = 1000; // 1 second
 pub const MAX_RETRIES: u32 = 5;
 
+/// Builds the pools at the addresses with a single DataSync deploy call
+///
+/// Transient provider errors are retried with backoff. A revert is returned right away
+/// since retrying the same addresses would revert again.
 pub async fn build_pools<P, T, N>(
     provider: &Arc<P>,
     addresses: Vec<Address>,
@@ -46,9 +47,8 @@ where
                 return Ok(pools);
             }
             Err(e) => {
-                if retry_count >= MAX_RETRIES {
-                    eprintln!("Max retries reached. Error: {:?} {:?}", e, addresses);
-                    return Ok(Vec::new());
+                if retry_count >= MAX_RETRIES || e.to_string().contains("revert") {
+                    return Err(e);
                 }
 
                 let jitter = rand::thread_rng().gen_range(0..=100);
@@ -142,57 +142,58 @@ where
         }
     }
 
-    // fill in missing info for the pool, this is more impl specific details. fetched by the full node, okay to not batch
-    for pool in &mut pools {
-        let token0_contract = ERC20::new(pool.token0_address(), &provider);
-        if let Ok(ERC20::symbolReturn { _0: name }) = token0_contract.symbol().call().await {
-            Pool::update_token0_name(pool, name);
-        }
-
-        let token1_contract = ERC20::new(pool.token1_address(), &provider);
-        if let Ok(ERC20::symbolReturn { _0: name }) = token1_contract.symbol().call().await {
-            Pool::update_token1_name(pool, name);
-        }
-
-        // If the pool is balancer, update names for the other tokens
-        if pool_type == PoolType::BalancerV2 {
-            let pool = pool.get_balancer_mut().unwrap();
-            for token in &pool.additional_tokens {
-                let token_contract = ERC20::new(*token, &provider);
-                if let Ok(ERC20::symbolReturn { _0: name }) = token_contract.symbol().call().await {
-                    pool.additional_token_names.push(name);
-                }
-            }
-        }
+    Ok(pools)
+}
 
-        // if the pool is curve, update name for the third token
-        if pool_type == PoolType::CurveTriCrypto {
-            let pool = pool.get_curve_tri_mut().unwrap();
-            let token_contract = ERC20::new(pool.token2, &provider);
-            if let Ok(ERC20::symbolReturn { _0: name }) = token_contract.symbol().call().await {
-                pool.token2_name = name;
+/// Reads whether the Aerodrome pools are stable and their fee
+///
+/// Every read is a call of its own that waits for a permit of the budget. Pools whose
+/// reads fail are returned by address so that they are retried later.
+pub async fn fill_aerodrome_pools<P, T, N>(
+    provider: &Arc<P>,
+    pools: Vec<Pool>,
+    budget: &RpcBudget,
+) -> (Vec<Pool>, Vec<Address>)
+where
+    P: Provider<T, N> + Sync + 'static,
+    T: Transport + Sync + Clone,
+    N: Network,
+{
+    let mut filled = Vec::new();
+    let mut failed = Vec::new();
+    for mut pool in pools {
+        match fill_aerodrome_pool(provider, &mut pool, budget).await {
+            Ok(()) => filled.push(pool),
+            Err(e) => {
+                info!("Failed to read the fee of Aerodrome pool {}: {}", pool.address(), e);
+                failed.push(pool.address());
             }
         }
-
-        // if the pool is aerodrome, update the fee and if it is stable or not
-        if pool_type == PoolType::Aerodrome {
-            let factory = address!("420DD381b31aEf6683db6B902084cB0FFECe40Da");
-            let pool = pool.get_v2_mut().unwrap();
-            // get if it is stable or not
-            let pool_contract = AerodromePool::new(pool.address, &provider);
-            let AerodromePool::stableReturn { _0: stable } =
-                pool_contract.stable().call().await.unwrap();
-            pool.stable = Some(stable);
-
-            let factory_contract = AerodromeV2Factory::new(factory, &provider);
-            let AerodromeV2Factory::getFeeReturn { _0: fee } = factory_contract
-                .getFee(pool.address, stable)
-                .call()
-                .await
-                .unwrap();
-            pool.fee = Some(fee);
-        }
     }
+    (filled, failed)
+}
 
-    Ok(pools)
+async fn fill_aerodrome_pool<P, T, N>(provider: &Arc<P>, pool: &mut Pool, budget: &RpcBudget) -> Result<()>
+where
+    P: Provider<T, N> + Sync + 'static,
+    T: Transport + Sync + Clone,
+    N: Network,
+{
+    let factory = address!("420DD381b31aEf6683db6B902084cB0FFECe40Da");
+    let pool = pool.get_v2_mut().unwrap();
+    // get if it is stable or not
+    let pool_contract = AerodromePool::new(pool.address, provider);
+    let AerodromePool::stableReturn { _0: stable } = {
+        let _permit = budget.acquire().await;
+        pool_contract.stable().call().await?
+    };
+    pool.stable = Some(stable);
+
+    let factory_contract = AerodromeV2Factory::new(factory, provider);
+    let AerodromeV2Factory::getFeeReturn { _0: fee } = {
+        let _permit = budget.acquire().await;
+        factory_contract.getFee(pool.address, stable).call().await?
+    };
+    pool.fee = Some(fee);
+    Ok(())
 }
@@ -23,6 +23,13 @@ impl PoolFetcher for AerodromeFetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 3_200_559,
+            _ => panic!("Aerodome not supported on this chain")
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         AerodromeV2Factory::PoolCreated::SIGNATURE
     }
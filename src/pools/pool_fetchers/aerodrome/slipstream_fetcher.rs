@@ -22,6 +22,13 @@ impl PoolFetcher for SlipstreamFetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 13_843_704,
+            _ => panic!("Aerodome not supported on this chain")
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         SlipstreamFactory::PoolCreated::SIGNATURE
     }
@@ -23,6 +23,13 @@ impl PoolFetcher for AlienBaseV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 1_500_000,
+            _=> panic!("AlienBase not supported on this chain")
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         AlienBaseV2Factory::PairCreated::SIGNATURE
     }
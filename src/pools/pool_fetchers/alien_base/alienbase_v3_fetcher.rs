@@ -20,6 +20,13 @@ impl PoolFetcher for AlienBaseV3Fetcher {
             _ => panic!("Alienbase not supported on this chain")
         }
     }
+
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 2_000_000,
+            _ => panic!("Alienbase not supported on this chain")
+        }
+    }
     
     fn pair_created_signature(&self) -> &str {
         AlienBaseV3Factory::PoolCreated::SIGNATURE
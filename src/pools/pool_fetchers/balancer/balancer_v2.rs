@@ -8,6 +8,9 @@ use crate::pools::PoolFetcher;
 use crate::pools::PoolType;
 use crate::Chain;
 
+/// The Balancer V2 Vault, it holds the balances of every pool and emits their events
+pub const BALANCER_V2_VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+
 pub struct BalancerV2Fetcher;
 
 impl PoolFetcher for BalancerV2Fetcher {
@@ -22,6 +25,13 @@ impl PoolFetcher for BalancerV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 16_500_000,
+            Chain::Base => 1_000_000,
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         BalancerV2Factory::PoolCreated::SIGNATURE
     }
@@ -1,2 +1,2 @@
-pub use balancer_v2::BalancerV2Fetcher;
+pub use balancer_v2::{BalancerV2Fetcher, BALANCER_V2_VAULT};
 mod balancer_v2;
\ No newline at end of file
@@ -21,6 +21,13 @@ impl PoolFetcher for BaseSwapV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 2_059_124,
+            _ => panic!("BaseSwap not supported on this chain")
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         BaseSwapV2Factory::PairCreated::SIGNATURE
     }
@@ -22,6 +22,13 @@ impl PoolFetcher for BaseSwapV3Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 2_000_000,
+            _ => panic!("Aerodome not supported on this chain")
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         BaseSwapV3Factory::PoolCreated::SIGNATURE
     }
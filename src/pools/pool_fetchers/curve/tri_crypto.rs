@@ -22,6 +22,13 @@ impl PoolFetcher for CurveTriCryptoFetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 17_000_000,
+            Chain::Base => 1_000_000,
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         TriCryptoFactory::TricryptoPoolDeployed::SIGNATURE
     }
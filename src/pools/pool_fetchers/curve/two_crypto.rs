@@ -21,6 +21,13 @@ impl PoolFetcher for CurveTwoCryptoFetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 18_500_000,
+            Chain::Base => 1_000_000,
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         TwoCryptoFactory::TwocryptoPoolDeployed::SIGNATURE
     }
@@ -21,6 +21,13 @@ impl PoolFetcher for DackieSwapV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 1_500_000,
+            _ => panic!("DackieSwap not supported on this chain")
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         DackieSwapV2Factory::PairCreated::SIGNATURE
     }
@@ -38,6 +38,13 @@ impl PoolFetcher for MaverickV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 19_000_000,
+            Chain::Base => 12_000_000,
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         PoolCreated::SIGNATURE
         
@@ -21,6 +21,13 @@ impl PoolFetcher for PancakeSwapV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 15_614_590,
+            Chain::Base => 2_910_387,
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         PancakeSwapV2Factory::PairCreated::SIGNATURE
     }
@@ -20,6 +20,13 @@ impl PoolFetcher for PancakeSwapV3Fetcher {
             Chain::Base => address!("0BFbCF9fa4f9C56B0F40a671Ad40E0805A091865"),
         }
     }
+
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 16_950_686,
+            Chain::Base => 2_912_007,
+        }
+    }
     
     fn pair_created_signature(&self) -> &str {
         PancakeSwapV3Factory::PoolCreated::SIGNATURE
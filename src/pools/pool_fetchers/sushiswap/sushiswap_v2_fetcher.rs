@@ -19,6 +19,13 @@ impl PoolFetcher for SushiSwapV2Fetcher {
             Chain::Base => address!("71524B4f93c58fcbF659783284E38825f0622859"),
         }
     }
+
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 10_794_229,
+            Chain::Base => 2_631_214,
+        }
+    }
     
     fn pair_created_signature(&self) -> &str {
         SushiSwapV2Factory::PairCreated::SIGNATURE
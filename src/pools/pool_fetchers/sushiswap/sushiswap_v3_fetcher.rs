@@ -20,6 +20,13 @@ impl PoolFetcher for SushiSwapV3Fetcher {
             Chain::Base => address!("c35DADB65012eC5796536bD9864eD8773aBc74C4"),
         }
     }
+
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 16_955_547,
+            Chain::Base => 1_759_510,
+        }
+    }
     
     fn pair_created_signature(&self) -> &str {
         SushiSwapV3Factory::PoolCreated::SIGNATURE
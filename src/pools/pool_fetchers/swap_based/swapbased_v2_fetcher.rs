@@ -21,6 +21,13 @@ impl PoolFetcher for SwapBasedV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Base => 1_500_000,
+            _ => panic!("SwapBased not supported on this chain")
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         BaseSwapV2Factory::PairCreated::SIGNATURE
     }
@@ -21,6 +21,13 @@ impl PoolFetcher for UniswapV2Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 10_000_835,
+            Chain::Base => 6_601_915,
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         UniswapV2Factory::PairCreated::SIGNATURE
     }
@@ -20,6 +20,13 @@ impl PoolFetcher for UniswapV3Fetcher {
         }
     }
 
+    fn deployment_block(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Ethereum => 12_369_621,
+            Chain::Base => 1_371_680,
+        }
+    }
+
     fn pair_created_signature(&self) -> &str {
         UniswapV3Factory::PoolCreated::SIGNATURE
     }
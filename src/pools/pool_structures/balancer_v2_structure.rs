@@ -1,11 +1,13 @@
 use alloy::primitives::FixedBytes;
-use alloy::primitives::U256;
+use alloy::primitives::{I256, U256};
 use alloy::rpc::types::Log;
 use alloy::sol_types::SolEvent;
 use alloy::{dyn_abi::DynSolValue, primitives::Address};
 use serde::{Deserialize, Serialize};
 
-use crate::pools::gen::Vault;
+use crate::errors::PoolSyncError;
+use crate::events::BalancerV2Event;
+use crate::pools::PoolType;
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BalancerV2Pool {
     pub address: Address,
@@ -54,16 +56,68 @@ impl BalancerV2Pool {
     }
 }
 
-pub fn process_balance_data(pool: &mut BalancerV2Pool, log: Log) {
-    let event = Vault::Swap::decode_log(log.as_ref(), true).unwrap();
+/// Applies a Vault or pool event to the balances and swap fee of the pool
+///
+/// Swaps, joins, exits and asset manager operations are emitted by the Vault and carry
+/// the pool id, swap fee changes are emitted by the pool itself.
+pub fn process_balance_data(pool: &mut BalancerV2Pool, log: Log) -> Result<(), PoolSyncError> {
+    let decode_err = |e: &dyn std::fmt::Display| PoolSyncError::decode(PoolType::BalancerV2, &log, e);
+    let token_index = |pool: &BalancerV2Pool, token: &Address| {
+        pool.get_token_index(token)
+            .ok_or_else(|| decode_err(&format!("token {} is not in the pool", token)))
+    };
 
-    let log_token_in_idx = pool.get_token_index(&event.tokenIn).unwrap();
-    let log_token_out_idx = pool.get_token_index(&event.tokenOut).unwrap();
+    match log.topic0() {
+        Some(&BalancerV2Event::Swap::SIGNATURE_HASH) => {
+            let event = BalancerV2Event::Swap::decode_log(log.as_ref(), true)
+                .map_err(|e| decode_err(&e))?;
+            let token_in = token_index(pool, &event.tokenIn)?;
+            let token_out = token_index(pool, &event.tokenOut)?;
 
-    pool.balances[log_token_in_idx] =
-        pool.balances[log_token_in_idx].saturating_add(event.amountIn);
-    pool.balances[log_token_out_idx] =
-        pool.balances[log_token_out_idx].saturating_sub(event.amountOut);
+            pool.balances[token_in] = pool.balances[token_in].saturating_add(event.amountIn);
+            pool.balances[token_out] = pool.balances[token_out].saturating_sub(event.amountOut);
+        }
+        Some(&BalancerV2Event::PoolBalanceChanged::SIGNATURE_HASH) => {
+            let event = BalancerV2Event::PoolBalanceChanged::decode_log(log.as_ref(), true)
+                .map_err(|e| decode_err(&e))?;
+
+            // joins have positive and exits negative deltas, protocol fees are paid on top
+            for ((token, delta), fee) in event
+                .tokens
+                .iter()
+                .zip(event.deltas.iter())
+                .zip(event.protocolFeeAmounts.iter())
+            {
+                let index = token_index(pool, token)?;
+                let balance = apply_delta(pool.balances[index], *delta);
+                pool.balances[index] = balance.saturating_sub(*fee);
+            }
+        }
+        Some(&BalancerV2Event::PoolBalanceManaged::SIGNATURE_HASH) => {
+            let event = BalancerV2Event::PoolBalanceManaged::decode_log(log.as_ref(), true)
+                .map_err(|e| decode_err(&e))?;
+
+            // the pool balance is the sum of the cash and the managed balance
+            let index = token_index(pool, &event.token)?;
+            let balance = apply_delta(pool.balances[index], event.cashDelta);
+            pool.balances[index] = apply_delta(balance, event.managedDelta);
+        }
+        Some(&BalancerV2Event::SwapFeePercentageChanged::SIGNATURE_HASH) => {
+            let event = BalancerV2Event::SwapFeePercentageChanged::decode_log(log.as_ref(), true)
+                .map_err(|e| decode_err(&e))?;
+            pool.swap_fee = event.swapFeePercentage;
+        }
+        _ => return Err(decode_err(&"unknown event")),
+    }
+    Ok(())
+}
+
+fn apply_delta(balance: U256, delta: I256) -> U256 {
+    if delta.is_negative() {
+        balance.saturating_sub(delta.unsigned_abs())
+    } else {
+        balance.saturating_add(delta.unsigned_abs())
+    }
 }
 
 impl From<&[DynSolValue]> for BalancerV2Pool {
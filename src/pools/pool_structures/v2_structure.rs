@@ -1,4 +1,5 @@
 
+use crate::errors::PoolSyncError;
 use crate::events::{AerodromeSync, DataEvents};
 use crate::pools::PoolType;
 use alloy::dyn_abi::DynSolValue;
@@ -22,16 +23,23 @@ pub struct UniswapV2Pool {
     pub fee: Option<U256>,
 }
 
-pub fn process_sync_data(pool: &mut UniswapV2Pool, log: Log, pool_type: PoolType) {
+pub fn process_sync_data(
+    pool: &mut UniswapV2Pool,
+    log: Log,
+    pool_type: PoolType,
+) -> Result<(), PoolSyncError> {
     let (reserve0, reserve1) = if pool_type == PoolType::Aerodrome {
-        let sync_event =  AerodromeSync::Sync::decode_log(log.as_ref(), true).unwrap();
+        let sync_event = AerodromeSync::Sync::decode_log(log.as_ref(), true)
+            .map_err(|e| PoolSyncError::decode(pool_type, &log, e))?;
         (sync_event.reserve0, sync_event.reserve1)
     } else {
-        let sync_event = DataEvents::Sync::decode_log(log.as_ref(), true).unwrap();
+        let sync_event = DataEvents::Sync::decode_log(log.as_ref(), true)
+            .map_err(|e| PoolSyncError::decode(pool_type, &log, e))?;
         (U256::from(sync_event.reserve0), U256::from(sync_event.reserve1))
     };
     pool.token0_reserves = reserve0;
     pool.token1_reserves = reserve1;
+    Ok(())
 }
 
 impl From<&[DynSolValue]> for UniswapV2Pool {
@@ -5,6 +5,7 @@ use alloy::sol_types::SolEvent;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::errors::PoolSyncError;
 use crate::events::{DataEvents, PancakeSwapEvents};
 use crate::pools::PoolType;
 
@@ -38,22 +39,32 @@ pub fn process_tick_data(
     log: Log,
     pool_type: PoolType,
     is_initial_sync: bool,
-) {
-    let event_sig = log.topic0().unwrap();
-
-    if *event_sig == DataEvents::Burn::SIGNATURE_HASH {
-        process_burn(pool, log, is_initial_sync);
-    } else if *event_sig == DataEvents::Mint::SIGNATURE_HASH {
-        process_mint(pool, log, is_initial_sync);
-    } else if *event_sig == DataEvents::Swap::SIGNATURE_HASH
-        || *event_sig == PancakeSwapEvents::Swap::SIGNATURE_HASH
+) -> Result<(), PoolSyncError> {
+    let event_sig = *log
+        .topic0()
+        .ok_or_else(|| PoolSyncError::decode(pool_type, &log, "log has no topics"))?;
+
+    if event_sig == DataEvents::Burn::SIGNATURE_HASH {
+        process_burn(pool, log, pool_type, is_initial_sync)
+    } else if event_sig == DataEvents::Mint::SIGNATURE_HASH {
+        process_mint(pool, log, pool_type, is_initial_sync)
+    } else if event_sig == DataEvents::Swap::SIGNATURE_HASH
+        || event_sig == PancakeSwapEvents::Swap::SIGNATURE_HASH
     {
-        process_swap(pool, log, pool_type);
+        process_swap(pool, log, pool_type)
+    } else {
+        Ok(())
     }
 }
 
-fn process_burn(pool: &mut UniswapV3Pool, log: Log, is_initial_sync: bool) {
-    let burn_event = DataEvents::Burn::decode_log(log.as_ref(), true).unwrap();
+fn process_burn(
+    pool: &mut UniswapV3Pool,
+    log: Log,
+    pool_type: PoolType,
+    is_initial_sync: bool,
+) -> Result<(), PoolSyncError> {
+    let burn_event = DataEvents::Burn::decode_log(log.as_ref(), true)
+        .map_err(|e| PoolSyncError::decode(pool_type, &log, e))?;
     modify_position(
         pool,
         burn_event.tickLower.unchecked_into(),
@@ -61,10 +72,17 @@ fn process_burn(pool: &mut UniswapV3Pool, log: Log, is_initial_sync: bool) {
         -(burn_event.amount as i128),
         is_initial_sync,
     );
+    Ok(())
 }
 
-fn process_mint(pool: &mut UniswapV3Pool, log: Log, is_initial_sync: bool) {
-    let mint_event = DataEvents::Mint::decode_log(log.as_ref(), true).unwrap();
+fn process_mint(
+    pool: &mut UniswapV3Pool,
+    log: Log,
+    pool_type: PoolType,
+    is_initial_sync: bool,
+) -> Result<(), PoolSyncError> {
+    let mint_event = DataEvents::Mint::decode_log(log.as_ref(), true)
+        .map_err(|e| PoolSyncError::decode(pool_type, &log, e))?;
     modify_position(
         pool,
         mint_event.tickLower.unchecked_into(),
@@ -72,20 +90,28 @@ fn process_mint(pool: &mut UniswapV3Pool, log: Log, is_initial_sync: bool) {
         mint_event.amount as i128,
         is_initial_sync,
     );
+    Ok(())
 }
 
-fn process_swap(pool: &mut UniswapV3Pool, log: Log, pool_type: PoolType) {
+fn process_swap(
+    pool: &mut UniswapV3Pool,
+    log: Log,
+    pool_type: PoolType,
+) -> Result<(), PoolSyncError> {
     if pool_type == PoolType::PancakeSwapV3 {
-        let swap_event = PancakeSwapEvents::Swap::decode_log(log.as_ref(), true).unwrap();
+        let swap_event = PancakeSwapEvents::Swap::decode_log(log.as_ref(), true)
+            .map_err(|e| PoolSyncError::decode(pool_type, &log, e))?;
         pool.tick = swap_event.tick.as_i32();
         pool.sqrt_price = U256::from(swap_event.sqrtPriceX96);
         pool.liquidity = swap_event.liquidity;
     } else {
-        let swap_event = DataEvents::Swap::decode_log(log.as_ref(), true).unwrap();
+        let swap_event = DataEvents::Swap::decode_log(log.as_ref(), true)
+            .map_err(|e| PoolSyncError::decode(pool_type, &log, e))?;
         pool.tick = swap_event.tick.as_i32();
         pool.sqrt_price = U256::from(swap_event.sqrtPriceX96);
         pool.liquidity = swap_event.liquidity;
     }
+    Ok(())
 }
 
 /// Modifies a positions liquidity in the pool.
@@ -0,0 +1,313 @@
+//! V3 Tick Snapshots
+//!
+//! Reads the state and the initialized ticks of V3 pools at a pinned block. The initialized
+//! ticks are found by the UniswapV3TickSync helper, which walks the tick bitmap outward from
+//! the current tick on chain and is run as a deploy call. The state and the liquidity of
+//! the ticks of many pools are packed into multicalls, so bootstrapping a pool takes a few
+//! eth_calls instead of replaying every Mint and Burn since its factory was deployed.
+
+use alloy::eips::BlockId;
+use alloy::network::Network;
+use alloy::primitives::aliases::I24;
+use alloy::primitives::{Address, Bytes, I256, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::state::StateOverride;
+use alloy::sol;
+use alloy::sol_types::{SolCall, SolValue};
+use alloy::transports::Transport;
+use futures::future::try_join_all;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::errors::PoolSyncError;
+use crate::multicall::{self, word};
+use crate::pools::gen::UniswapV3TickSync;
+use crate::pools::pool_structures::v3_structure::TickInfo;
+use crate::pools::Pool;
+use crate::rpc::RpcBudget;
+
+/// Tick range of every V3 pool
+pub(crate) const MIN_TICK: i32 = -887272;
+const MAX_TICK: i32 = 887272;
+
+/// Ticks walked by a single UniswapV3TickSync call, each one costs about a bitmap word read
+const TICKS_PER_WALK: u16 = 1000;
+
+sol! {
+    // only the leading return values are read, they are the same for every V3 fork
+    interface IV3TickState {
+        function slot0() external view returns (uint160 sqrtPriceX96, int24 tick);
+        function liquidity() external view returns (uint128);
+        function ticks(int24 tick) external view returns (uint128 liquidityGross, int128 liquidityNet);
+    }
+
+    // a step of the walk, an uninitialized tick is the edge of a bitmap word
+    struct TickData {
+        bool initialized;
+        int24 tick;
+        int128 liquidityNet;
+    }
+}
+
+/// State of a V3 pool at the snapshot block
+#[derive(Debug, Default)]
+pub struct V3Snapshot {
+    pub sqrt_price: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    pub tick_bitmap: HashMap<i16, U256>,
+    pub ticks: HashMap<i32, TickInfo>,
+}
+
+/// Reads the price, tick and active liquidity of the pools
+pub async fn v3_state_snapshot<P, T, N>(
+    provider: &Arc<P>,
+    pools: &[Address],
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<Vec<(U256, i32, u128)>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let calls = pools
+        .iter()
+        .flat_map(|pool| {
+            [
+                (*pool, IV3TickState::slot0Call {}.abi_encode()),
+                (*pool, IV3TickState::liquidityCall {}.abi_encode()),
+            ]
+        })
+        .collect();
+    let results = aggregate(provider, calls, block, budget).await?;
+
+    results
+        .chunks(2)
+        .map(|result| {
+            let sqrt_price = word(&result[0], 0)?;
+            let tick = I256::from_raw(word(&result[0], 1)?).as_i32();
+            let liquidity = word(&result[1], 0)?.to::<u128>();
+            Ok((sqrt_price, tick, liquidity))
+        })
+        .collect()
+}
+
+/// Finds the initialized ticks of a pool, walking down and then up from the current tick
+///
+/// Every call walks at most `TICKS_PER_WALK` steps, a walk that did not reach the end of
+/// the tick range is continued from its last step.
+pub async fn v3_tick_walk<P, T, N>(
+    provider: &Arc<P>,
+    pool: Address,
+    tick: i32,
+    tick_spacing: i32,
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<Vec<i32>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let mut ticks = Vec::new();
+    for zero_for_one in [true, false] {
+        let mut start = tick;
+        while (MIN_TICK..=MAX_TICK).contains(&start) {
+            let data = {
+                let _permit = budget.acquire().await;
+                UniswapV3TickSync::deploy_builder(
+                    provider.clone(),
+                    pool,
+                    zero_for_one,
+                    int24(start)?,
+                    TICKS_PER_WALK,
+                    int24(tick_spacing)?,
+                )
+                .block(BlockId::number(block))
+                .call()
+                .await
+                .map_err(|e| PoolSyncError::ProviderError(e.to_string()))?
+            };
+            let (steps, last) = decode_walk(&data)?;
+            ticks.extend(steps);
+
+            // the walk continues below or above the last step, the up walk starts above its
+            // tick
+            start = match last {
+                Some(last) if zero_for_one => last - 1,
+                Some(last) if last > start => last,
+                Some(_) => {
+                    return Err(PoolSyncError::ProviderError(format!(
+                        "Tick walk of {} did not advance past tick {}",
+                        pool, start
+                    )))
+                }
+                None => break,
+            };
+        }
+    }
+    // a walk that was continued can return its last tick again, read every tick only once
+    ticks.sort_unstable();
+    ticks.dedup();
+    Ok(ticks)
+}
+
+/// Decodes the steps of a walk into its initialized ticks and the tick it stopped at,
+/// `None` if it reached the end of the tick range
+pub(crate) fn decode_walk(data: &Bytes) -> Result<(Vec<i32>, Option<i32>), PoolSyncError> {
+    let (steps, _block) = <(Vec<TickData>, U256)>::abi_decode_params(data, true)
+        .map_err(|e| PoolSyncError::ProviderError(format!("Invalid tick walk: {}", e)))?;
+
+    let mut ticks = Vec::new();
+    let mut last = None;
+    for step in steps {
+        let tick = step.tick.as_i32();
+        if !step.initialized && tick == MIN_TICK {
+            return Ok((ticks, None));
+        }
+        if step.initialized {
+            ticks.push(tick);
+        }
+        last = Some(tick);
+    }
+    Ok((ticks, last))
+}
+
+/// Reads the liquidity of the given ticks, each given with the address of its pool
+pub async fn v3_tick_snapshot<P, T, N>(
+    provider: &Arc<P>,
+    ticks: &[(Address, i32)],
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<Vec<TickInfo>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let calls = ticks
+        .iter()
+        .map(|(pool, tick)| Ok((*pool, IV3TickState::ticksCall { tick: int24(*tick)? }.abi_encode())))
+        .collect::<Result<_, PoolSyncError>>()?;
+    let results = aggregate(provider, calls, block, budget).await?;
+
+    results
+        .iter()
+        .map(|result| {
+            let liquidity_gross = word(result, 0)?.to::<u128>();
+            let liquidity_net = i128::try_from(I256::from_raw(word(result, 1)?))
+                .map_err(|e| PoolSyncError::ProviderError(e.to_string()))?;
+            Ok(TickInfo {
+                liquidity_net,
+                initialized: true,
+                liquidity_gross,
+            })
+        })
+        .collect()
+}
+
+/// Replaces the state and ticks of the V3 pools with their state at the block
+pub async fn snapshot_v3_pools<P, T, N>(
+    provider: &Arc<P>,
+    pools: &mut [Pool],
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<(), PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let specs: Vec<(Address, i32)> = pools
+        .iter()
+        .filter_map(|pool| pool.get_v3().map(|pool| (pool.address, pool.tick_spacing)))
+        .collect();
+    let addresses: Vec<Address> = specs.iter().map(|(address, _)| *address).collect();
+
+    let states = v3_state_snapshot(provider, &addresses, block, budget).await?;
+    let walks = try_join_all(specs.iter().zip(&states).map(|((address, tick_spacing), state)| {
+        v3_tick_walk(provider, *address, state.1, *tick_spacing, block, budget)
+    }))
+    .await?;
+
+    let ticks: Vec<(Address, i32)> = specs
+        .iter()
+        .zip(&walks)
+        .flat_map(|((address, _), ticks)| ticks.iter().map(move |tick| (*address, *tick)))
+        .collect();
+    let tick_infos = v3_tick_snapshot(provider, &ticks, block, budget).await?;
+
+    let mut snapshots: HashMap<Address, V3Snapshot> = HashMap::new();
+    for (((address, tick_spacing), (sqrt_price, tick, liquidity)), walk) in
+        specs.iter().zip(states).zip(walks)
+    {
+        let snapshot = V3Snapshot {
+            sqrt_price,
+            tick,
+            liquidity,
+            tick_bitmap: tick_bitmap(walk, *tick_spacing),
+            ticks: HashMap::new(),
+        };
+        snapshots.insert(*address, snapshot);
+    }
+    for ((address, tick), info) in ticks.into_iter().zip(tick_infos) {
+        snapshots.get_mut(&address).unwrap().ticks.insert(tick, info);
+    }
+
+    for pool in pools.iter_mut().filter_map(|pool| pool.get_v3_mut()) {
+        if let Some(snapshot) = snapshots.remove(&pool.address) {
+            pool.sqrt_price = snapshot.sqrt_price;
+            pool.tick = snapshot.tick;
+            pool.liquidity = snapshot.liquidity;
+            pool.tick_bitmap = snapshot.tick_bitmap;
+            pool.ticks = snapshot.ticks;
+        }
+    }
+    Ok(())
+}
+
+/// Tick bitmap with the bits of the initialized ticks set
+pub(crate) fn tick_bitmap(
+    ticks: impl IntoIterator<Item = i32>,
+    tick_spacing: i32,
+) -> HashMap<i16, U256> {
+    let mut bitmap: HashMap<i16, U256> = HashMap::new();
+    for tick in ticks {
+        let (word, bit) = uniswap_v3_math::tick_bitmap::position(tick / tick_spacing.max(1));
+        *bitmap.entry(word).or_default() |= U256::from(1) << bit;
+    }
+    bitmap
+}
+
+fn int24(value: i32) -> Result<I24, PoolSyncError> {
+    I24::try_from(value)
+        .map_err(|_| PoolSyncError::ProviderError(format!("{} is outside the int24 range", value)))
+}
+
+/// Runs the reads at the block, every one of them has to succeed
+async fn aggregate<P, T, N>(
+    provider: &Arc<P>,
+    calls: Vec<(Address, Vec<u8>)>,
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<Vec<Bytes>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let calls = calls
+        .into_iter()
+        .map(|(target, data)| multicall::call(target, data, false))
+        .collect();
+    let results = multicall::aggregate(
+        provider,
+        calls,
+        BlockId::number(block),
+        StateOverride::default(),
+        budget,
+    )
+    .await?;
+    Ok(results.into_iter().map(|result| result.returnData).collect())
+}
@@ -0,0 +1,250 @@
+//! Chain Reorganization Handling
+//!
+//! This module tracks the hashes of recently synced blocks and keeps per block undo
+//! journals for the pool state that was modified by recent logs. When the chain
+//! reorganizes, the journals are used to roll the pools back to the common ancestor
+//! so that the logs of the new canonical chain can be applied on top.
+
+use alloy::primitives::{Address, B256, U256};
+use alloy::rpc::types::Log;
+use alloy::sol_types::SolEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use crate::events::DataEvents;
+use crate::pools::pool_structures::v3_structure::{TickInfo, UniswapV3Pool};
+use crate::pools::{Pool, PoolInfo};
+
+/// Number of blocks behind the head that are tracked for reorgs
+pub const REORG_WINDOW: u64 = 64;
+
+/// Hashes of the most recently synced blocks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockWindow {
+    blocks: VecDeque<(u64, B256)>,
+}
+
+impl BlockWindow {
+    /// Records the hash of a synced block, dropping blocks that fell out of the window
+    pub fn record(&mut self, number: u64, hash: B256) {
+        self.truncate(number.saturating_sub(1));
+        self.blocks.push_back((number, hash));
+        while let Some(&(oldest, _)) = self.blocks.front() {
+            if oldest + REORG_WINDOW >= number {
+                break;
+            }
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Forgets all blocks after the given block number
+    pub fn truncate(&mut self, number: u64) {
+        while let Some(&(newest, _)) = self.blocks.back() {
+            if newest <= number {
+                break;
+            }
+            self.blocks.pop_back();
+        }
+    }
+
+    /// Returns the recorded blocks from newest to oldest
+    pub fn iter_newest(&self) -> impl Iterator<Item = &(u64, B256)> {
+        self.blocks.iter().rev()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+/// State of a pool before a log was applied to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum UndoEntry {
+    /// The pool was created in this block and has to be removed on rollback
+    Created(Address),
+    /// Reserves of a v2 style pool
+    V2 {
+        address: Address,
+        token0_reserves: U256,
+        token1_reserves: U256,
+    },
+    /// Price, liquidity and the ticks touched in a v3 style pool
+    V3 {
+        address: Address,
+        liquidity: u128,
+        sqrt_price: U256,
+        tick: i32,
+        ticks: Vec<(i32, Option<TickInfo>)>,
+        words: Vec<(i16, Option<U256>)>,
+    },
+    /// Token balances and swap fee of a balancer pool
+    Balancer {
+        address: Address,
+        balances: Vec<U256>,
+        #[serde(default)]
+        swap_fee: Option<U256>,
+    },
+}
+
+impl UndoEntry {
+    fn address(&self) -> Address {
+        match self {
+            UndoEntry::Created(address)
+            | UndoEntry::V2 { address, .. }
+            | UndoEntry::V3 { address, .. }
+            | UndoEntry::Balancer { address, .. } => *address,
+        }
+    }
+}
+
+/// Per block journal of the changes applied to a set of pools
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UndoJournal {
+    blocks: BTreeMap<u64, Vec<UndoEntry>>,
+}
+
+impl UndoJournal {
+    /// Captures the state of the pool that is about to be modified by the log
+    pub fn record(&mut self, block: u64, pool: &Pool, log: &Log) {
+        let entry = if let Some(pool) = pool.get_v2() {
+            UndoEntry::V2 {
+                address: pool.address,
+                token0_reserves: pool.token0_reserves,
+                token1_reserves: pool.token1_reserves,
+            }
+        } else if let Some(pool) = pool.get_v3() {
+            capture_v3(pool, log)
+        } else if let Some(pool) = pool.get_balancer() {
+            UndoEntry::Balancer {
+                address: pool.address,
+                balances: pool.balances.clone(),
+                swap_fee: Some(pool.swap_fee),
+            }
+        } else {
+            return;
+        };
+        self.blocks.entry(block).or_default().push(entry);
+    }
+
+    /// Records that a pool was created in the block
+    pub fn record_created(&mut self, block: u64, address: Address) {
+        self.blocks
+            .entry(block)
+            .or_default()
+            .push(UndoEntry::Created(address));
+    }
+
+    /// Appends the entries of a journal that was recorded after this one
+    pub fn append(&mut self, other: UndoJournal) {
+        for (block, entries) in other.blocks {
+            self.blocks.entry(block).or_default().extend(entries);
+        }
+    }
+
+    /// Reverts every change made after the ancestor block, newest first
+    ///
+    /// Returns the addresses of the pools that were reverted or dropped.
+    pub fn rollback(&mut self, pools: &mut Vec<Pool>, ancestor: u64) -> HashSet<Address> {
+        let reverted = self.blocks.split_off(&(ancestor + 1));
+        let mut touched = HashSet::new();
+        for entry in reverted.into_values().rev().flat_map(|e| e.into_iter().rev()) {
+            touched.insert(entry.address());
+            match entry {
+                UndoEntry::Created(address) => pools.retain(|pool| pool.address() != address),
+                UndoEntry::V2 {
+                    address,
+                    token0_reserves,
+                    token1_reserves,
+                } => {
+                    if let Some(pool) = find(pools, address).and_then(|p| p.get_v2_mut()) {
+                        pool.token0_reserves = token0_reserves;
+                        pool.token1_reserves = token1_reserves;
+                    }
+                }
+                UndoEntry::V3 {
+                    address,
+                    liquidity,
+                    sqrt_price,
+                    tick,
+                    ticks,
+                    words,
+                } => {
+                    if let Some(pool) = find(pools, address).and_then(|p| p.get_v3_mut()) {
+                        pool.liquidity = liquidity;
+                        pool.sqrt_price = sqrt_price;
+                        pool.tick = tick;
+                        for (tick, info) in ticks {
+                            match info {
+                                Some(info) => pool.ticks.insert(tick, info),
+                                None => pool.ticks.remove(&tick),
+                            };
+                        }
+                        for (word_pos, word) in words {
+                            match word {
+                                Some(word) => pool.tick_bitmap.insert(word_pos, word),
+                                None => pool.tick_bitmap.remove(&word_pos),
+                            };
+                        }
+                    }
+                }
+                UndoEntry::Balancer {
+                    address,
+                    balances,
+                    swap_fee,
+                } => {
+                    if let Some(pool) = find(pools, address).and_then(|p| p.get_balancer_mut()) {
+                        pool.balances = balances;
+                        if let Some(swap_fee) = swap_fee {
+                            pool.swap_fee = swap_fee;
+                        }
+                    }
+                }
+            }
+        }
+        touched
+    }
+
+    /// Drops the journals of blocks that can no longer be reorganized
+    pub fn prune(&mut self, head: u64) {
+        let oldest = head.saturating_sub(REORG_WINDOW);
+        self.blocks = self.blocks.split_off(&oldest);
+    }
+}
+
+fn find(pools: &mut [Pool], address: Address) -> Option<&mut Pool> {
+    pools.iter_mut().find(|pool| pool.address() == address)
+}
+
+// Mints and burns only touch the ticks at their bounds, so those are the only ticks
+// and bitmap words that have to be saved
+fn capture_v3(pool: &UniswapV3Pool, log: &Log) -> UndoEntry {
+    let bounds = if let Ok(mint) = DataEvents::Mint::decode_log(log.as_ref(), true) {
+        vec![mint.tickLower.as_i32(), mint.tickUpper.as_i32()]
+    } else if let Ok(burn) = DataEvents::Burn::decode_log(log.as_ref(), true) {
+        vec![burn.tickLower.as_i32(), burn.tickUpper.as_i32()]
+    } else {
+        Vec::new()
+    };
+
+    let ticks = bounds
+        .iter()
+        .map(|tick| (*tick, pool.ticks.get(tick).cloned()))
+        .collect();
+    let words = bounds
+        .iter()
+        .filter(|_| pool.tick_spacing != 0)
+        .map(|tick| {
+            let (word_pos, _) = uniswap_v3_math::tick_bitmap::position(tick / pool.tick_spacing);
+            (word_pos, pool.tick_bitmap.get(&word_pos).copied())
+        })
+        .collect();
+
+    UndoEntry::V3 {
+        address: pool.address,
+        liquidity: pool.liquidity,
+        sqrt_price: pool.sqrt_price,
+        tick: pool.tick,
+        ticks,
+        words,
+    }
+}
@@ -0,0 +1,85 @@
+//! Sync Reports
+//!
+//! This module defines the report returned by a sync. It holds the synced pools of
+//! every chain together with per pool type statistics about what the sync did.
+
+use alloy::primitives::Address;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::errors::PoolSyncError;
+use crate::{Chain, Pool, PoolType, TokenRegistry};
+
+/// Outcome of syncing a single pool type
+#[derive(Debug, Default)]
+pub struct PoolTypeReport {
+    /// Blocks that were applied during this sync, `None` if the cache was already current
+    pub synced_blocks: Option<RangeInclusive<u64>>,
+    /// Block the pool type is synced up to
+    pub last_synced_block: u64,
+    /// Number of pools discovered during this sync
+    pub new_pools: usize,
+    /// Number of existing pools whose state was updated
+    pub updated_pools: usize,
+    /// Pools whose data could not be fetched, they are retried on the next sync
+    pub pending_pools: Vec<Address>,
+    /// Number of rpc requests sent for the pool type
+    pub rpc_calls: u64,
+    /// Time spent syncing the pool type
+    pub elapsed: Duration,
+    /// Error that stopped the pool type from syncing, its cache keeps the last good state
+    pub error: Option<PoolSyncError>,
+    /// Damaged cache file that was kept aside, the pool type resumed from the previous
+    /// generation of its cache and resynced the blocks since
+    pub recovered_from: Option<PathBuf>,
+}
+
+/// Outcome of syncing a single chain
+#[derive(Debug, Default)]
+pub struct ChainReport {
+    /// All pools of the chain
+    pub pools: Vec<Pool>,
+    /// Statistics for every pool type synced on the chain
+    pub pool_types: HashMap<PoolType, PoolTypeReport>,
+}
+
+impl ChainReport {
+    /// Block that every pool type of the chain is synced up to
+    pub fn last_synced_block(&self) -> u64 {
+        self.pool_types
+            .values()
+            .map(|report| report.last_synced_block)
+            .min()
+            .unwrap_or_default()
+    }
+}
+
+/// Outcome of a sync across all chains
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Report of every synced chain
+    pub chains: HashMap<Chain, ChainReport>,
+    /// Metadata of the tokens in the pools of every chain
+    pub tokens: TokenRegistry,
+}
+
+impl SyncReport {
+    /// Iterates over the pools of all chains
+    pub fn pools(&self) -> impl Iterator<Item = &Pool> {
+        self.chains.values().flat_map(|chain| chain.pools.iter())
+    }
+
+    /// Iterates over the pool types that failed to sync
+    pub fn failures(&self) -> impl Iterator<Item = (Chain, PoolType, &PoolSyncError)> {
+        self.chains.iter().flat_map(|(chain, report)| {
+            report
+                .pool_types
+                .iter()
+                .filter_map(move |(pool_type, report)| {
+                    report.error.as_ref().map(|e| (*chain, *pool_type, e))
+                })
+        })
+    }
+}
@@ -1,26 +1,32 @@
-use alloy::network::Network;
-use alloy::primitives::Address;
+use alloy::network::primitives::HeaderResponse;
+use alloy::network::{BlockResponse, Network};
+use alloy::primitives::{Address, B256};
 use alloy::providers::Provider;
-use alloy::rpc::types::{Filter, Log};
+use alloy::rpc::types::{BlockTransactionsKind, Filter, Log};
 use alloy::sol_types::SolEvent;
 use alloy::transports::Transport;
-use anyhow::anyhow;
-use anyhow::Result;
+use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use indicatif::ProgressBar;
 use log::info;
 use rand::Rng;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{Mutex, Semaphore};
-use tokio::time::{interval, Duration};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Duration;
 
+use crate::endpoints::EndpointPool;
+use crate::errors::PoolSyncError;
 use crate::events::*;
 use crate::pools::pool_builder;
 use crate::pools::pool_structures::balancer_v2_structure::process_balance_data;
 use crate::pools::pool_structures::v2_structure::process_sync_data;
 use crate::pools::pool_structures::v3_structure::process_tick_data;
+use crate::pools::pool_fetchers::BALANCER_V2_VAULT;
+use crate::pools::snapshot;
 use crate::pools::PoolFetcher;
+use crate::reorg::{BlockWindow, UndoJournal, REORG_WINDOW};
 use crate::util::create_progress_bar;
 use crate::{Chain, Pool, PoolInfo, PoolType};
 
@@ -28,6 +34,102 @@ use crate::{Chain, Pool, PoolInfo, PoolType};
 const MAX_RETRIES: u32 = 5;
 const INITIAL_BACKOFF: u64 = 1000; // 1 second
 
+// Adaptive get_logs ranges
+const SPARSE_LOG_COUNT: usize = 1000; // ranges with fewer logs than this grow the step
+const MAX_STEP_GROWTH: u64 = 64; // the step grows to at most this multiple of the configured one
+const REORDER_WINDOW: usize = 256; // ranges fetched or buffered ahead of the next one to apply
+
+// Address scoped log filters
+const MAX_FILTER_ADDRESSES: usize = 1000; // addresses per filter most providers accept
+const ADDRESS_STEP_MULTIPLIER: u64 = 10; // filtered ranges return far fewer logs
+
+// Liquidity sync
+const SNAPSHOT_BATCH_SIZE: usize = 50; // v3 pools whose ticks are read in one snapshot
+pub(crate) const LIQUIDITY_BATCH_SIZE: u64 = 1_000_000; // blocks of logs applied between checkpoints
+
+/// Reason a provider rejected a get_logs request
+#[derive(Debug, PartialEq)]
+pub(crate) enum LogLimit {
+    /// The block range is larger than the provider allows
+    Range,
+    /// The range matched more logs than the provider returns at once
+    Results,
+}
+
+/// Sorts logs into the order they were emitted on chain
+///
+/// State is only correct if logs are applied strictly in this order, e.g. a swap has to
+/// see the liquidity of a mint earlier in the same block.
+pub(crate) fn sort_logs(logs: &mut [Log]) {
+    logs.sort_by_key(|log| (log.block_number, log.transaction_index, log.log_index));
+}
+
+/// Classifies the error of a get_logs request that was rejected for being too large
+///
+/// Rate limit errors are not a limit of the request, they return `None` so that the
+/// request is retried with backoff instead of being split.
+pub(crate) fn log_limit(message: &str) -> Option<LogLimit> {
+    const RATE_LIMIT_ERRORS: &[&str] = &[
+        "rate limit",
+        "too many requests",
+        "error 429",
+        "compute units",
+        "throttl",
+    ];
+    const RANGE_ERRORS: &[&str] = &[
+        "block range",
+        "range is too large",
+        "range too large",
+        "range exceeds",
+        "exceed maximum block range",
+        "limited to a",
+    ];
+    const RESULT_ERRORS: &[&str] = &[
+        "query returned more than",
+        "response size exceeded",
+        "query exceeds max results",
+        "query timeout",
+    ];
+
+    // result errors often mention the block range as a hint, so they are checked first
+    let message = message.to_lowercase();
+    if RATE_LIMIT_ERRORS.iter().any(|pattern| message.contains(pattern)) {
+        None
+    } else if RESULT_ERRORS.iter().any(|pattern| message.contains(pattern)) {
+        Some(LogLimit::Results)
+    } else if RANGE_ERRORS.iter().any(|pattern| message.contains(pattern)) {
+        Some(LogLimit::Range)
+    } else {
+        None
+    }
+}
+
+/// Which contracts the logs of a liquidity sync are requested for
+#[derive(Debug, PartialEq)]
+pub(crate) enum LogScope {
+    /// Every log with a matching signature on the chain
+    Global,
+    /// Only logs from these addresses, one filter per chunk
+    Addresses {
+        chunks: Vec<Vec<Address>>,
+        step_size: u64,
+    },
+}
+
+impl LogScope {
+    /// Number of get_logs requests needed to cover the block range at the starting step
+    /// size, one per filter and step
+    fn requests(&self, start_block: u64, end_block: u64, global_step: u64) -> u64 {
+        let blocks = end_block - start_block + 1;
+        match self {
+            LogScope::Global => blocks.div_ceil(global_step),
+            LogScope::Addresses { chunks, step_size } => {
+                blocks.div_ceil(*step_size) * chunks.len() as u64
+            }
+        }
+    }
+}
+
 // Define event configurations
 #[derive(Debug)]
 struct EventConfig {
@@ -37,6 +139,78 @@ struct EventConfig {
     requires_initial_sync: bool,
 }
 
+/// Request budget shared by every rpc task of a sync
+///
+/// All pool types syncing at the same time draw from the same budget, which bounds the
+/// number of requests in flight and counts the requests sent. The request rate itself is
+/// enforced by the limiter in the transport of each provider.
+#[derive(Clone)]
+pub struct RpcBudget {
+    semaphore: Arc<Semaphore>,
+    concurrency: usize,
+    calls: Arc<AtomicU64>,
+    /// Endpoints that serve the get_logs requests and know their block range limits
+    log_endpoints: Option<EndpointPool>,
+}
+
+impl RpcBudget {
+    /// Creates a budget that allows `concurrency` requests in flight
+    pub fn new(concurrency: u64) -> Self {
+        let concurrency = concurrency.max(1) as usize;
+        Self {
+            semaphore: Arc::new(Semaphore::new(concurrency)),
+            concurrency,
+            calls: Arc::new(AtomicU64::new(0)),
+            log_endpoints: None,
+        }
+    }
+
+    /// Sizes get_logs requests by the block range limits learned by the endpoints
+    pub(crate) fn with_log_endpoints(self, endpoints: EndpointPool) -> Self {
+        Self {
+            log_endpoints: Some(endpoints),
+            ..self
+        }
+    }
+
+    /// Returns a view of the same budget with its own request counter
+    pub fn scoped(&self) -> Self {
+        Self {
+            calls: Arc::new(AtomicU64::new(0)),
+            ..self.clone()
+        }
+    }
+
+    /// Waits until a request may be sent, the permit must be held until it completes
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        let permit = self.semaphore.acquire().await.unwrap();
+        self.record_call();
+        permit
+    }
+
+    /// Counts a request that was sent without acquiring a new permit, e.g. a retry
+    pub fn record_call(&self) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of requests sent through this budget
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// Largest block range an endpoint accepts for get_logs
+    pub fn log_range_cap(&self) -> u64 {
+        self.log_endpoints
+            .as_ref()
+            .map_or(u64::MAX, |endpoints| endpoints.log_range_cap())
+    }
+
+    /// Maximum number of requests that can be in flight at once
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+}
+
 pub struct Rpc;
 impl Rpc {
     // Fetch all pool addresses for the protocol
@@ -46,8 +220,8 @@ impl Rpc {
         provider: Arc<P>,
         fetcher: Arc<dyn PoolFetcher>,
         chain: Chain,
-        rate_limit: u64,
-    ) -> Result<Vec<Address>>
+        budget: RpcBudget,
+    ) -> Result<Vec<Address>, PoolSyncError>
     where
         P: Provider<T, N> + 'static,
         T: Transport + Clone + 'static,
@@ -58,8 +232,7 @@ impl Rpc {
             .address(fetcher.factory_address(chain))
             .event(fetcher.pair_created_signature());
 
-        let step_size: u64 = 10000;
-        let num_tasks = end_block / step_size;
+        let num_tasks = end_block - start_block + 1;
         let pb_info = format!(
             "{} Address Sync. Block range {}-{}",
             fetcher.pool_type(),
@@ -68,34 +241,41 @@ impl Rpc {
         );
         let progress_bar = Arc::new(create_progress_bar(num_tasks, pb_info));
 
-        // fetch all of the logs
-        let logs = Rpc::fetch_event_logs(
+        // extract the addresses from the logs, skipping any that were reorged out
+        let mut addresses = Vec::new();
+        Rpc::fetch_event_logs(
             start_block,
             end_block,
             10000,
             provider,
-            rate_limit,
+            budget,
             progress_bar,
-            filter,
+            vec![filter],
+            |logs| {
+                addresses.extend(
+                    logs.iter()
+                        .filter(|log| !log.removed)
+                        .map(|log| fetcher.log_to_address(&log.inner)),
+                );
+                Ok(())
+            },
         )
-        .await?;
-
-        // extract the addresses from the logs
-        let addresses: Vec<Address> = logs
-            .iter()
-            .map(|log| fetcher.log_to_address(&log.inner))
-            .collect();
-        anyhow::Ok(addresses)
+        .await
+        .map_err(|e| PoolSyncError::rpc(fetcher.pool_type(), start_block, end_block, e))?;
+        Ok(addresses)
     }
 
+    /// Builds the pools at the given addresses in batches
+    ///
+    /// Returns the built pools together with the addresses that could not be built.
     pub async fn populate_pools<P, T, N>(
         pool_addrs: Vec<Address>,
         provider: Arc<P>,
         pool: PoolType,
         fetcher: Arc<dyn PoolFetcher>,
-        rate_limit: u64,
+        budget: RpcBudget,
         chain: Chain
-    ) -> Result<Vec<Pool>>
+    ) -> Result<(Vec<Pool>, Vec<Address>), PoolSyncError>
     where
         P: Provider<T, N> + 'static,
         T: Transport + Clone + 'static,
@@ -105,12 +285,9 @@ impl Rpc {
         let batch_size = if pool.is_balancer() { 10 } else { 50 };
 
         // informational and rate limiting initialization
-        let total_tasks = (pool_addrs.len() + batch_size - 1) / batch_size; // Ceiling division
+        let total_tasks = pool_addrs.len().div_ceil(batch_size);
         let progress_bar = create_progress_bar(total_tasks as u64, format!("{} data sync", pool));
-        let semaphore = Arc::new(Semaphore::new(rate_limit as usize));
-        let interval = Arc::new(tokio::sync::Mutex::new(interval(Duration::from_secs_f64(
-            1.0 / rate_limit as f64,
-        ))));
+        let concurrency = budget.concurrency();
 
         // break the addresses up into chunk
         let addr_chunks: Vec<Vec<Address>> = pool_addrs
@@ -120,80 +297,153 @@ impl Rpc {
 
         let mut stream = futures::stream::iter(addr_chunks.into_iter().map(|chunk| {
             let provider = provider.clone();
-            let sem = semaphore.clone();
+            let budget = budget.clone();
             let pb = progress_bar.clone();
             let fetcher = fetcher.clone();
-            let interval = interval.clone();
             let data = fetcher.get_pool_repr();
 
             async move {
-                let _permit = sem.acquire().await.unwrap();
-                interval.lock().await.tick().await;
-                let mut retry_count = 0;
-                let mut backoff = 1000; // Initial backoff of 1 second
-                loop {
-                    // try building pools from this set of addresses
-                    match pool_builder::build_pools(
-                        &provider,
-                        chunk.clone(),
-                        pool,
-                        data.clone(),
-                        chain
-                    )
-                    .await
+                let permit = budget.acquire().await;
+                let mut pools = Vec::new();
+                let mut failed = Vec::new();
+
+                // a single pool that makes the DataSync constructor revert fails the whole
+                // batch, so failed batches are split in half until the bad pool is isolated
+                let mut batches = vec![chunk];
+                let mut first_call = true;
+                while let Some(batch) = batches.pop() {
+                    if !first_call {
+                        budget.record_call();
+                    }
+                    first_call = false;
+
+                    match pool_builder::build_pools(&provider, batch.clone(), pool, data.clone(), chain)
+                        .await
                     {
-                        Ok(populated_pools) if !populated_pools.is_empty() => {
-                            pb.inc(1);
-                            drop(provider);
-                            return anyhow::Ok::<Vec<Pool>>(populated_pools);
+                        Ok(populated_pools) => pools.extend(populated_pools),
+                        Err(_) if batch.len() > 1 => {
+                            let (left, right) = batch.split_at(batch.len() / 2);
+                            batches.push(right.to_vec());
+                            batches.push(left.to_vec());
                         }
                         Err(e) => {
-                            if retry_count >= MAX_RETRIES {
-                                info!("Failed to populate pools data: {}", e);
-                                drop(provider);
-                                return Ok(Vec::new());
-                            }
-                            let jitter = rand::thread_rng().gen_range(0..=100);
-                            let sleep_duration = Duration::from_millis(backoff + jitter);
-                            tokio::time::sleep(sleep_duration).await;
-                            retry_count += 1;
-                            backoff *= 2; // Exponential backoff
+                            info!("Failed to populate {} pool {}: {}", pool, batch[0], e);
+                            failed.push(batch[0]);
                         }
-                        _ => continue,
                     }
                 }
+                drop(permit);
+
+                // the fee of aerodrome pools is read per pool, every read takes a permit
+                if pool == PoolType::Aerodrome {
+                    let (filled, unfilled) =
+                        pool_builder::fill_aerodrome_pools(&provider, pools, &budget).await;
+                    pools = filled;
+                    failed.extend(unfilled);
+                }
+
+                pb.inc(1);
+                Ok::<_, PoolSyncError>((pools, failed))
             }
         }))
-        .buffer_unordered(rate_limit as usize);
+        .buffer_unordered(concurrency);
 
         let mut all_pools = Vec::new();
+        let mut dropped = Vec::new();
 
         while let Some(pool_res) = stream.next().await {
             match pool_res {
-                Ok(pool) => all_pools.extend(pool),
+                Ok((pools, failed)) => {
+                    all_pools.extend(pools);
+                    dropped.extend(failed);
+                }
                 Err(e) => return Err(e),
             }
         }
 
-        Ok(all_pools)
+        Ok((all_pools, dropped))
     }
 
+    /// Bootstraps the ticks of V3 pools from a snapshot of their storage at the block
+    ///
+    /// The pools are snapshotted in batches. Returns the snapshotted pools together with the
+    /// pools of the batches that failed, those have to replay their Mint and Burn logs.
+    pub async fn snapshot_v3_pools<P, T, N>(
+        pools: Vec<Pool>,
+        block: u64,
+        provider: Arc<P>,
+        pool_type: PoolType,
+        budget: RpcBudget,
+    ) -> (Vec<Pool>, Vec<Pool>)
+    where
+        P: Provider<T, N> + 'static,
+        T: Transport + Clone + 'static,
+        N: Network,
+    {
+        if pools.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+
+        let total_tasks = pools.len().div_ceil(SNAPSHOT_BATCH_SIZE);
+        let progress_bar = create_progress_bar(total_tasks as u64, format!("{} tick snapshot", pool_type));
+        let concurrency = budget.concurrency();
+
+        let mut pools = pools.into_iter().peekable();
+        let mut batches: Vec<Vec<Pool>> = Vec::new();
+        while pools.peek().is_some() {
+            batches.push(pools.by_ref().take(SNAPSHOT_BATCH_SIZE).collect());
+        }
+
+        // the snapshot calls acquire their own permits
+        let mut stream = futures::stream::iter(batches.into_iter().map(|mut batch| {
+            let provider = provider.clone();
+            let budget = budget.clone();
+            let pb = progress_bar.clone();
+            async move {
+                let result = snapshot::snapshot_v3_pools(&provider, &mut batch, block, &budget).await;
+                pb.inc(1);
+                (batch, result)
+            }
+        }))
+        .buffer_unordered(concurrency);
+
+        let mut snapshotted = Vec::new();
+        let mut replay = Vec::new();
+        while let Some((batch, result)) = stream.next().await {
+            match result {
+                Ok(()) => snapshotted.extend(batch),
+                Err(e) => {
+                    info!(
+                        "Failed to snapshot {} ticks at block {}, replaying their logs instead: {}",
+                        pool_type, block, e
+                    );
+                    replay.extend(batch);
+                }
+            }
+        }
+        (snapshotted, replay)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn populate_liquidity<P, T, N>(
         start_block: u64,
         end_block: u64,
         pools: &mut [Pool],
         provider: Arc<P>,
         pool_type: PoolType,
-        rate_limit: u64,
+        budget: RpcBudget,
         is_initial_sync: bool,
-    ) -> anyhow::Result<()>
+        journal: Option<&mut UndoJournal>,
+    ) -> Result<HashSet<Address>, PoolSyncError>
     where
         P: Provider<T, N> + Sync + 'static,
         T: Transport + Sync + Clone,
         N: Network,
     {
+        // addresses of all the pools that had state applied to them
+        let mut touched = HashSet::new();
         if pools.is_empty() {
-            return anyhow::Ok(());
+            return Ok(touched);
         }
 
         let address_to_index: HashMap<Address, usize> = pools
@@ -202,176 +452,355 @@ impl Rpc {
             .map(|(i, pool)| (pool.address(), i))
             .collect();
 
-        let batch_size = 1_000_000;
-        let mut current_block = start_block;
+        // vault events name the balancer pool by its id instead of its address
+        let pool_id_to_index: HashMap<B256, usize> = pools
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pool)| pool.get_balancer().map(|pool| (pool.pool_id, i)))
+            .collect();
 
         // get the configuration for this sync and config we should sync
         let config = Rpc::get_event_config(pool_type, is_initial_sync);
         if is_initial_sync && config.requires_initial_sync {
-            return anyhow::Ok(());
+            return Ok(touched);
         }
 
-        // construct the progress bar
-        let num_tasks = (end_block - start_block) / config.step_size;
+        // only ask for the logs of our own pools when that takes fewer requests
+        let scope = Rpc::get_log_scope(pool_type, pools, start_block, end_block, config.step_size);
+
+        // construct the progress bar, progress is counted in blocks
+        let num_tasks = end_block - start_block + 1;
         let pb_info = format!(
             "{} {}. Block range {}-{}",
-            pool_type, config.description, current_block, end_block
+            pool_type, config.description, start_block, end_block
         );
         let progress_bar = Arc::new(create_progress_bar(num_tasks, pb_info));
 
-        // sync in batches
-        while current_block <= end_block {
-            let batch_end = (current_block + batch_size).min(end_block);
-
-            let logs = Rpc::fetch_logs_for_config(
-                &config,
-                current_block,
-                batch_end,
-                provider.clone(),
-                progress_bar.clone(),
-                rate_limit,
-            )
-            .await?;
-
-            // create pb for block processing
-            let processing_pb_info = format!(
-                "Processing logs batch for blocks {}-{}",
-                start_block, end_block
-            );
-            let processing_progress_bar =
-                create_progress_bar(logs.len().try_into().unwrap(), processing_pb_info);
-
-            // Process logs immediately after fetching
-            let mut ordered_logs: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
-            for log in logs {
-                if let Some(block_number) = log.block_number {
-                    ordered_logs.entry(block_number).or_default().push(log);
-                }
-            }
+        // apply the logs as they arrive, they are handed over in chain order. They are applied
+        // to copies of the pools, which are only written back once every log was fetched, so
+        // a failed or cancelled fetch leaves the pools as they were
+        let mut staged: HashMap<usize, Pool> = HashMap::new();
+        let mut staged_journal = UndoJournal::default();
+        Rpc::fetch_logs_for_config(
+            &config,
+            &scope,
+            start_block,
+            end_block,
+            provider,
+            progress_bar,
+            budget,
+            |logs| {
+                for log in logs {
+                    // logs from blocks that were reorged out must not be applied
+                    let Some(block_number) = log.block_number.filter(|_| !log.removed) else {
+                        continue;
+                    };
+
+                    let index = if log.address() == BALANCER_V2_VAULT {
+                        log.topics().get(1).and_then(|pool_id| pool_id_to_index.get(pool_id))
+                    } else {
+                        address_to_index.get(&log.address())
+                    };
+                    let Some(&index) = index else {
+                        continue;
+                    };
+                    let pool = staged
+                        .entry(index)
+                        .or_insert_with(|| pools[index].clone());
+
+                    let address = pool.address();
+                    // save the state of pools modified by blocks that can still be reorged
+                    if journal.is_some() && block_number + REORG_WINDOW > end_block {
+                        staged_journal.record(block_number, pool, &log);
+                    }
 
-            // Process logs in order
-            for (_, log_group) in ordered_logs {
-                for log in log_group {
-                    let address = log.address();
-                    if let Some(&index) = address_to_index.get(&address) {
-                        if let Some(pool) = pools.get_mut(index) {
-                            if pool_type.is_v3() {
-                                process_tick_data(
-                                    pool.get_v3_mut().unwrap(),
-                                    log,
-                                    pool_type,
-                                    is_initial_sync,
-                                );
-                            } else if pool_type.is_balancer() {
-                                process_balance_data(pool.get_balancer_mut().unwrap(), log);
-                            } else {
-                                process_sync_data(pool.get_v2_mut().unwrap(), log, pool_type);
-                            }
-                        }
+                    if pool_type.is_v3() {
+                        process_tick_data(pool.get_v3_mut().unwrap(), log, pool_type, is_initial_sync)?;
+                    } else if pool_type.is_balancer() {
+                        process_balance_data(pool.get_balancer_mut().unwrap(), log)?;
+                    } else {
+                        process_sync_data(pool.get_v2_mut().unwrap(), log, pool_type)?;
                     }
-                    processing_progress_bar.inc(1);
+                    touched.insert(address);
                 }
-            }
+                Ok(())
+            },
+        )
+        .await
+        .map_err(|e| match e {
+            e @ PoolSyncError::DecodeError { .. } => e,
+            e => PoolSyncError::rpc(pool_type, start_block, end_block, e),
+        })?;
 
-            processing_progress_bar.finish_and_clear();
-            current_block = batch_end + 1;
+        for (index, pool) in staged {
+            pools[index] = pool;
+        }
+        if let Some(journal) = journal {
+            journal.append(staged_journal);
         }
-        anyhow::Ok(())
+        Ok(touched)
     }
 
-    pub async fn fetch_event_logs<T, N, P>(
+    /// Streams all logs matching the filters in the block range to `on_logs`
+    ///
+    /// The range is requested in chunks of `step_size` blocks, each chunk with every filter.
+    /// Chunks that the provider rejects as too large are bisected, and the chunk size grows
+    /// again over stretches with few logs. Block range limits that the endpoints reported
+    /// are remembered by their endpoint pool and respected by every later request.
+    ///
+    /// Chunks are fetched concurrently but handed to `on_logs` in chain order, as soon as
+    /// every chunk before them has been handed over. At most `REORDER_WINDOW` chunks are in
+    /// flight or waiting for an earlier one, which bounds the logs held in memory.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn fetch_event_logs<T, N, P, F>(
         start_block: u64,
         end_block: u64,
         step_size: u64,
         provider: Arc<P>,
-        rate_limit: u64,
+        budget: RpcBudget,
         progress_bar: Arc<ProgressBar>,
-        filter: Filter,
-    ) -> anyhow::Result<Vec<Log>>
+        filters: Vec<Filter>,
+        mut on_logs: F,
+    ) -> Result<(), PoolSyncError>
     where
         T: Transport + Clone,
         N: Network,
         P: Provider<T, N> + 'static,
+        F: FnMut(Vec<Log>) -> Result<(), PoolSyncError>,
     {
-        // generate the block range for the sync and setup progress bar
-        let block_range = Rpc::get_block_range(step_size, start_block, end_block);
-
-        // semaphore and interval for rate limiting
-        let semaphore = Arc::new(Semaphore::new(rate_limit as usize));
-        let interval = Arc::new(Mutex::new(interval(Duration::from_secs_f64(
-            1.0 / rate_limit as f64,
-        ))));
-
-        // Create a stream of futures
-        let mut stream =
-            futures::stream::iter(block_range.into_iter().map(|(from_block, to_block)| {
-                let provider = provider.clone();
-                let sem = semaphore.clone();
-                let pb = progress_bar.clone();
-                let interval = interval.clone();
-                let filter = filter.clone();
-
-                async move {
-                    let _permit = sem.acquire().await.unwrap();
-                    interval.lock().await.tick().await;
-
-                    let filter = filter.from_block(from_block).to_block(to_block);
-                    let logs = Rpc::get_logs_with_retry(provider, &filter).await;
-                    if logs.is_ok() {
-                        pb.inc(1);
+        let concurrency = budget.concurrency();
+        let max_step = step_size.max(1) * MAX_STEP_GROWTH;
+        let filters = Arc::new(filters);
+        let mut step = step_size.max(1);
+        let mut next_block = start_block;
+        let mut split_ranges: Vec<(u64, u64)> = Vec::new();
+        let mut in_flight = FuturesUnordered::new();
+
+        // fetched chunks keyed by their first block, waiting for the chunks before them
+        let mut ready: BTreeMap<u64, (u64, Vec<Log>)> = BTreeMap::new();
+        let mut next_ready = start_block;
+
+        loop {
+            // hand out bisected ranges first, then continue from the cursor while the
+            // reorder window has room
+            while in_flight.len() < concurrency {
+                let (from_block, to_block) = match split_ranges.pop() {
+                    Some(range) => range,
+                    None if next_block <= end_block
+                        && in_flight.len() + ready.len() < REORDER_WINDOW =>
+                    {
+                        let span = step.min(budget.log_range_cap());
+                        let to_block = (next_block + span - 1).min(end_block);
+                        let range = (next_block, to_block);
+                        next_block = to_block + 1;
+                        range
                     }
-                    logs
-                }
-            }))
-            .buffer_unordered(rate_limit as usize); // Process up to rate_limit tasks concurrently
+                    None => break,
+                };
 
-        let mut all_logs = Vec::new();
+                let provider = provider.clone();
+                let budget = budget.clone();
+                let filters = filters.clone();
+                in_flight.push(async move {
+                    let mut logs = Vec::new();
+                    // every filter is its own request and waits for its own permit
+                    for filter in filters.iter() {
+                        let filter = filter.clone().from_block(from_block).to_block(to_block);
+                        let _permit = budget.acquire().await;
+                        match Rpc::get_logs_with_retry(provider.clone(), &filter, &budget).await {
+                            Ok(chunk) => logs.extend(chunk),
+                            Err(e) => return ((from_block, to_block), Err(e)),
+                        }
+                    }
+                    ((from_block, to_block), Ok(logs))
+                });
+            }
 
-        // Process results as they complete
-        while let Some(result) = stream.next().await {
+            let Some(((from_block, to_block), result)) = in_flight.next().await else {
+                break;
+            };
+            let span = to_block - from_block + 1;
             match result {
-                Ok(logs) => all_logs.extend(logs),
-                Err(e) => return Err(e),
+                Ok(mut logs) => {
+                    // sparse stretch, ask for more blocks at once
+                    if logs.len() < SPARSE_LOG_COUNT && span >= step {
+                        step = (step * 2).min(max_step);
+                    }
+                    progress_bar.inc(span);
+                    sort_logs(&mut logs);
+                    ready.insert(from_block, (to_block, logs));
+
+                    // hand over every chunk that continues the range handed over so far
+                    while let Some(entry) = ready.first_entry() {
+                        if *entry.key() != next_ready {
+                            break;
+                        }
+                        let (to_block, logs) = entry.remove();
+                        next_ready = to_block + 1;
+                        on_logs(logs)?;
+                    }
+                }
+                Err(e) => match log_limit(&e.to_string()) {
+                    Some(_) if span > 1 => {
+                        step = step.min(span / 2).max(1);
+                        let mid = from_block + span / 2;
+                        split_ranges.push((mid, to_block));
+                        split_ranges.push((from_block, mid - 1));
+                    }
+                    _ => return Err(e),
+                },
             }
         }
 
-        Ok(all_logs)
+        Ok(())
     }
 
-    // Given a config and a range, fetch all the logs for it
+    // Given a config and a range, stream all the logs for it
     // This is a top level call which will delegate to individual fetching
     // functions to get the logs and to ensure retries on failure
-    async fn fetch_logs_for_config<P, T, N>(
+    #[allow(clippy::too_many_arguments)]
+    async fn fetch_logs_for_config<P, T, N, F>(
         config: &EventConfig,
+        scope: &LogScope,
         start_block: u64,
         end_block: u64,
         provider: Arc<P>,
         progress_bar: Arc<ProgressBar>,
-        rate_limit: u64,
-    ) -> Result<Vec<Log>>
+        budget: RpcBudget,
+        on_logs: F,
+    ) -> Result<(), PoolSyncError>
     where
         P: Provider<T, N> + 'static,
         T: Transport + Clone + 'static,
         N: Network,
+        F: FnMut(Vec<Log>) -> Result<(), PoolSyncError>,
     {
         let filter = Filter::new().events(config.events.iter().copied());
+        let (filters, step_size) = match scope {
+            LogScope::Global => (vec![filter], config.step_size),
+            LogScope::Addresses { chunks, step_size } => (
+                chunks
+                    .iter()
+                    .map(|chunk| filter.clone().address(chunk.clone()))
+                    .collect(),
+                *step_size,
+            ),
+        };
         Rpc::fetch_event_logs(
             start_block,
             end_block,
-            config.step_size,
+            step_size,
             provider,
-            rate_limit,
+            budget,
             progress_bar,
-            filter,
+            filters,
+            on_logs,
         )
         .await
     }
 
+    /// Picks how the logs for a liquidity sync of the pools are requested
+    ///
+    /// Balancer balance changes are emitted by the vault. For every other protocol the pool
+    /// addresses are put into the filter when that needs no more requests than fetching
+    /// the events of the whole chain. Both are estimated from the block range at their
+    /// starting step sizes, a range shorter than a step costs a request per address chunk
+    /// but only one global request. How dense the logs are is not known up front, so the
+    /// steps growing over sparse stretches and splitting over dense ones is not counted.
+    pub(crate) fn get_log_scope(
+        pool_type: PoolType,
+        pools: &[Pool],
+        start_block: u64,
+        end_block: u64,
+        global_step: u64,
+    ) -> LogScope {
+        // the vault emits the balance changes and the pools their fee changes
+        if pool_type.is_balancer() {
+            let mut chunks = vec![vec![BALANCER_V2_VAULT]];
+            chunks.extend(
+                pools
+                    .chunks(MAX_FILTER_ADDRESSES)
+                    .map(|chunk| chunk.iter().map(|pool| pool.address()).collect()),
+            );
+            return LogScope::Addresses {
+                chunks,
+                step_size: global_step,
+            };
+        }
+
+        let scope = LogScope::Addresses {
+            chunks: pools
+                .chunks(MAX_FILTER_ADDRESSES)
+                .map(|chunk| chunk.iter().map(|pool| pool.address()).collect())
+                .collect(),
+            step_size: global_step * ADDRESS_STEP_MULTIPLIER,
+        };
+        if scope.requests(start_block, end_block, global_step)
+            <= LogScope::Global.requests(start_block, end_block, global_step)
+        {
+            scope
+        } else {
+            LogScope::Global
+        }
+    }
+
+    /// Finds the block the synced state has to be rolled back to after a reorg
+    ///
+    /// Compares the recorded hashes against the canonical chain from newest to oldest.
+    /// Returns `None` when the newest recorded block is still canonical and the block of
+    /// the most recent canonical entry otherwise.
+    pub async fn find_reorg_ancestor<P, T, N>(
+        window: &BlockWindow,
+        provider: Arc<P>,
+        budget: &RpcBudget,
+    ) -> Result<Option<u64>, PoolSyncError>
+    where
+        P: Provider<T, N> + 'static,
+        T: Transport + Clone + 'static,
+        N: Network,
+    {
+        for (i, (number, hash)) in window.iter_newest().enumerate() {
+            if Rpc::get_block_hash(*number, provider.clone(), budget).await? == *hash {
+                return Ok((i != 0).then_some(*number));
+            }
+        }
+
+        if window.is_empty() {
+            Ok(None)
+        } else {
+            Err(PoolSyncError::ProviderError(format!(
+                "Chain reorganized deeper than the {} tracked blocks",
+                REORG_WINDOW
+            )))
+        }
+    }
+
+    /// Fetches the canonical hash of a block
+    pub async fn get_block_hash<P, T, N>(
+        number: u64,
+        provider: Arc<P>,
+        budget: &RpcBudget,
+    ) -> Result<B256, PoolSyncError>
+    where
+        P: Provider<T, N> + 'static,
+        T: Transport + Clone + 'static,
+        N: Network,
+    {
+        let _permit = budget.acquire().await;
+        let block = provider
+            .get_block_by_number(number.into(), BlockTransactionsKind::Hashes)
+            .await
+            .map_err(|e| PoolSyncError::ProviderError(e.to_string()))?
+            .ok_or_else(|| PoolSyncError::ProviderError(format!("Block {} not found", number)))?;
+        Ok(block.header().hash())
+    }
+
     // Fetch logs with retry functionality
     async fn get_logs_with_retry<P, T, N>(
         provider: Arc<P>,
         filter: &Filter,
-    ) -> anyhow::Result<Vec<Log>>
+        budget: &RpcBudget,
+    ) -> Result<Vec<Log>, PoolSyncError>
     where
         P: Provider<T, N> + 'static,
         T: Transport + Clone + 'static,
@@ -383,17 +812,19 @@ impl Rpc {
         loop {
             match provider.get_logs(filter).await {
                 Ok(logs) => {
-                    return anyhow::Ok(logs);
+                    return Ok(logs);
                 }
                 Err(e) => {
-                    if retry_count >= MAX_RETRIES {
-                        return Err(anyhow!(e));
+                    // the same range would be rejected again, the caller has to split it
+                    if retry_count >= MAX_RETRIES || log_limit(&e.to_string()).is_some() {
+                        return Err(PoolSyncError::ProviderError(e.to_string()));
                     }
                     let jitter = rand::thread_rng().gen_range(0..=100);
                     let sleep_duration = Duration::from_millis(backoff + jitter);
                     tokio::time::sleep(sleep_duration).await;
                     retry_count += 1;
                     backoff *= 2;
+                    budget.record_call();
                 }
             }
         }
@@ -423,9 +854,14 @@ impl Rpc {
                 }
             }
             pt if pt.is_balancer() => EventConfig {
-                events: &[BalancerV2Event::Swap::SIGNATURE],
+                events: &[
+                    BalancerV2Event::Swap::SIGNATURE,
+                    BalancerV2Event::PoolBalanceChanged::SIGNATURE,
+                    BalancerV2Event::PoolBalanceManaged::SIGNATURE,
+                    BalancerV2Event::SwapFeePercentageChanged::SIGNATURE,
+                ],
                 step_size: 5000,
-                description: "Swap Sync",
+                description: "Balance Sync",
                 requires_initial_sync: true,
             },
             _ => EventConfig {
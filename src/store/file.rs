@@ -0,0 +1,292 @@
+//! File store, every pool type of a chain is kept in its own cache file
+
+use log::warn;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use super::format::{
+    self, CacheCompression, CacheEncoding, DecodeFailure, BINARY_LAYOUT_VERSION, FORMAT_VERSION,
+};
+use super::PoolStore;
+use crate::cache::PoolCache;
+use crate::chain::Chain;
+use crate::errors::PoolSyncError;
+use crate::pools::PoolType;
+use crate::tokens::TokenInfo;
+
+/// Keeps every pool type in `{chain}_{pool_type}.cache` in the directory, or in a
+/// subdirectory per profile
+///
+/// The whole file is rewritten on every write, atomically through a temporary file. The
+/// replaced file is kept as `.prev` and loaded instead if the current one fails its checksum,
+/// the damaged file is then kept as `.corrupt` and reported in the sync report.
+/// Caches are JSON by default, large caches are much smaller with the binary encoding and
+/// compression. Files written by an older version of the crate are upgraded the first time
+/// they are loaded.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+    profile: Option<String>,
+    encoding: CacheEncoding,
+    compression: CacheCompression,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            profile: None,
+            encoding: CacheEncoding::default(),
+            compression: CacheCompression::default(),
+        }
+    }
+
+    /// Keeps the caches in a subdirectory of their own, so that several configurations of
+    /// the same chain can share the directory
+    ///
+    /// The profile is recorded in every cache file and a cache of another profile is
+    /// refused when loaded.
+    pub fn profile(self, profile: &str) -> Self {
+        Self {
+            profile: Some(profile.to_string()),
+            ..self
+        }
+    }
+
+    /// Sets the encoding new cache files are written in, existing files are read whatever
+    /// their encoding
+    pub fn encoding(self, encoding: CacheEncoding) -> Self {
+        Self { encoding, ..self }
+    }
+
+    /// Compresses new cache files with gzip
+    pub fn compressed(self, compressed: bool) -> Self {
+        let compression = if compressed {
+            CacheCompression::Gzip
+        } else {
+            CacheCompression::None
+        };
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    fn profile_dir(&self) -> PathBuf {
+        match &self.profile {
+            Some(profile) => self.dir.join(profile),
+            None => self.dir.clone(),
+        }
+    }
+
+    fn cache_path(&self, chain: Chain, pool_type: PoolType) -> PathBuf {
+        self.profile_dir().join(format!("{}_{}.cache", chain, pool_type))
+    }
+
+    /// Caches written before the header was introduced
+    fn legacy_cache_path(&self, chain: Chain, pool_type: PoolType) -> PathBuf {
+        self.profile_dir().join(format!("{}_{}_cache.json", chain, pool_type))
+    }
+
+    fn token_path(&self, chain: Chain) -> PathBuf {
+        self.profile_dir().join(format!("{}_tokens.json", chain))
+    }
+
+    /// Reads and decodes a cache file, returning the version it was written in
+    fn read_cache(
+        &self,
+        chain: Chain,
+        pool_type: PoolType,
+        path: &Path,
+    ) -> Result<(PoolCache, u32), PoolSyncError> {
+        let corrupted = |message: String| PoolSyncError::CacheCorrupted {
+            chain,
+            pool_type,
+            path: path.display().to_string(),
+            message,
+        };
+
+        let file = std::fs::read(path)?;
+        if let Some((header, _)) = format::split_header(&file).map_err(corrupted)? {
+            if header.chain != chain || header.pool_type != pool_type {
+                return Err(corrupted(format!(
+                    "file holds the {} {} cache",
+                    header.chain, header.pool_type
+                )));
+            }
+            if header.profile != self.profile {
+                return Err(corrupted(format!(
+                    "file belongs to the {} profile",
+                    header.profile.as_deref().unwrap_or("default")
+                )));
+            }
+        }
+        format::decode(&file).map_err(|failure| match failure {
+            DecodeFailure::Unsupported(version) => PoolSyncError::UnsupportedCacheVersion {
+                path: path.display().to_string(),
+                version,
+                supported: FORMAT_VERSION,
+            },
+            DecodeFailure::UnsupportedBinary(version) => PoolSyncError::UnsupportedBinaryCache {
+                path: path.display().to_string(),
+                version,
+                oldest: BINARY_LAYOUT_VERSION,
+            },
+            DecodeFailure::Checksum { expected, actual } => PoolSyncError::CacheChecksumMismatch {
+                chain,
+                pool_type,
+                path: path.display().to_string(),
+                expected,
+                actual,
+            },
+            DecodeFailure::Corrupted(message) => corrupted(message),
+        })
+    }
+
+    /// Replaces the cache file, keeping the one it replaces as the previous generation
+    fn write_cache(&self, chain: Chain, cache: &PoolCache) -> Result<(), PoolSyncError> {
+        let path = self.cache_path(chain, cache.pool_type);
+        let file = format::encode(
+            cache,
+            chain,
+            self.profile.as_deref(),
+            self.encoding,
+            self.compression,
+        )?;
+        write_atomic(&path, &file, true)
+    }
+}
+
+impl Default for FileStore {
+    fn default() -> Self {
+        Self::new("cache")
+    }
+}
+
+impl PoolStore for FileStore {
+    fn load(&self, chain: Chain, pool_type: PoolType) -> Result<Option<PoolCache>, PoolSyncError> {
+        let path = self.cache_path(chain, pool_type);
+        let legacy = self.legacy_cache_path(chain, pool_type);
+        let previous = previous_generation(&path);
+        let (cache, version) = if path.exists() {
+            match self.read_cache(chain, pool_type, &path) {
+                Ok(decoded) => decoded,
+                Err(e) if is_damaged(&e) => {
+                    // the last write went through but the file was damaged since, fall back
+                    // to the generation it replaced
+                    if !previous.exists() {
+                        return Err(e);
+                    }
+                    let Ok((mut cache, version)) = self.read_cache(chain, pool_type, &previous)
+                    else {
+                        return Err(e);
+                    };
+                    // the damaged file is kept for inspection instead of being overwritten
+                    let corrupt = corrupt_path(&path);
+                    warn!(
+                        "{}, loading the previous generation and keeping the damaged file as {}",
+                        e,
+                        corrupt.display()
+                    );
+                    std::fs::rename(&path, &corrupt)?;
+                    std::fs::rename(&previous, &path)?;
+                    cache.recovered_from = Some(corrupt);
+                    (cache, version)
+                }
+                Err(e) => return Err(e),
+            }
+        } else if previous.exists() {
+            // interrupted between rotating the generations
+            let decoded = self.read_cache(chain, pool_type, &previous)?;
+            std::fs::rename(&previous, &path)?;
+            decoded
+        } else if legacy.exists() {
+            self.read_cache(chain, pool_type, &legacy)?
+        } else {
+            return Ok(None);
+        };
+
+        // upgrade older caches in place so the migration only runs once
+        if version < FORMAT_VERSION {
+            self.write_cache(chain, &cache)?;
+            if legacy.exists() {
+                std::fs::remove_file(&legacy)?;
+            }
+        }
+        Ok(Some(cache))
+    }
+
+    fn upsert(&self, chain: Chain, cache: &PoolCache) -> Result<(), PoolSyncError> {
+        self.write_cache(chain, cache)
+    }
+
+    fn load_tokens(&self, chain: Chain) -> Result<Vec<TokenInfo>, PoolSyncError> {
+        let token_cache_file = self.token_path(chain);
+        if !token_cache_file.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&token_cache_file)?;
+        let reader = BufReader::new(file);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn store_tokens(&self, chain: Chain, tokens: &[&TokenInfo]) -> Result<(), PoolSyncError> {
+        write_json(&self.token_path(chain), &tokens)
+    }
+}
+
+fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> Result<(), PoolSyncError> {
+    write_atomic(path, &serde_json::to_vec(value)?, false)
+}
+
+/// Writes the file next to its destination and renames it into place once it is on disk, so
+/// a crash leaves either the old or the new file but never a partial one
+///
+/// With `keep_previous` the replaced file is moved to its previous generation first.
+fn write_atomic(path: &Path, contents: &[u8], keep_previous: bool) -> Result<(), PoolSyncError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    if keep_previous {
+        match std::fs::rename(path, previous_generation(path)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    std::fs::rename(&temp, path)?;
+
+    // the rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+fn previous_generation(path: &Path) -> PathBuf {
+    let mut previous = path.as_os_str().to_owned();
+    previous.push(".prev");
+    previous.into()
+}
+
+/// Where a cache file that failed its checksum is set aside
+fn corrupt_path(path: &Path) -> PathBuf {
+    let mut corrupt = path.as_os_str().to_owned();
+    corrupt.push(".corrupt");
+    corrupt.into()
+}
+
+/// Whether the error comes from the contents of the file rather than from reading it
+fn is_damaged(e: &PoolSyncError) -> bool {
+    matches!(
+        e,
+        PoolSyncError::CacheCorrupted { .. } | PoolSyncError::CacheChecksumMismatch { .. }
+    )
+}
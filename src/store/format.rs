@@ -0,0 +1,221 @@
+//! Cache File Format
+//!
+//! Every cache file starts with a magic number and a small JSON header that describes the
+//! body: the format version, what the cache holds and how the body is encoded. The header
+//! stays readable whatever the encoding, so a cache written by an older version of the crate
+//! can be recognized and migrated instead of being thrown away. The header also carries a
+//! checksum of the body, so a cache that was damaged on disk is caught before it is decoded.
+
+use alloy::primitives::{keccak256, B256};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+use crate::cache::PoolCache;
+use crate::chain::Chain;
+use crate::errors::PoolSyncError;
+use crate::pools::PoolType;
+
+/// Marks a file as a cache file with a header, older caches are plain JSON
+const MAGIC: &[u8; 8] = b"POOLSYNC";
+
+/// Version of the cache body, bumped whenever the encoding of `PoolCache` changes
+///
+/// - 0: plain JSON without a header
+/// - 1: header followed by a JSON or bincode body
+/// - 2: header holds a checksum of the body
+///
+/// JSON bodies of every version are read into the current `PoolCache`, fields added since
+/// are filled with their defaults. Bincode has neither field names nor defaults, so a
+/// binary body can only be read with the layout it was written with.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// First format version whose binary body has the current layout of `PoolCache`
+///
+/// Raised to the new `FORMAT_VERSION` whenever a serialized field of the cache or the pools
+/// changes. Older binary caches are then refused with `UnsupportedBinaryCache` and have to
+/// be resynced, unless a migration from a frozen copy of their layout is added to `decode`.
+pub const BINARY_LAYOUT_VERSION: u32 = 1;
+
+/// How the body of a cache file is encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheEncoding {
+    /// Human readable, fields added to the pools are filled with their defaults on load
+    #[default]
+    Json,
+    /// Compact bincode, a lot smaller and faster for pools with many ticks. Caches written
+    /// before the last change to the layout of the pools are not read, see
+    /// `BINARY_LAYOUT_VERSION`
+    Binary,
+}
+
+/// How the body of a cache file is compressed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheCompression {
+    #[default]
+    None,
+    Gzip,
+}
+
+/// Describes the body of a cache file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheHeader {
+    pub format_version: u32,
+    pub chain: Chain,
+    pub pool_type: PoolType,
+    /// Profile the cache belongs to, `None` for the default profile
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Version of the crate that wrote the cache
+    pub crate_version: String,
+    /// Block the cache is synced up to
+    pub last_synced_block: u64,
+    pub encoding: CacheEncoding,
+    pub compression: CacheCompression,
+    /// Keccak256 of the body as stored, set from version 2 on
+    #[serde(default)]
+    pub checksum: Option<B256>,
+}
+
+/// Encodes the cache with its header
+pub(crate) fn encode(
+    cache: &PoolCache,
+    chain: Chain,
+    profile: Option<&str>,
+    encoding: CacheEncoding,
+    compression: CacheCompression,
+) -> Result<Vec<u8>, PoolSyncError> {
+    let body = match encoding {
+        CacheEncoding::Json => serde_json::to_vec(cache)?,
+        CacheEncoding::Binary => bincode::serialize(cache).map_err(encoding_error)?,
+    };
+    let body = match compression {
+        CacheCompression::None => body,
+        CacheCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&body)?;
+            encoder.finish()?
+        }
+    };
+
+    let header = CacheHeader {
+        format_version: FORMAT_VERSION,
+        chain,
+        pool_type: cache.pool_type,
+        profile: profile.map(str::to_string),
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        last_synced_block: cache.last_synced_block,
+        encoding,
+        compression,
+        checksum: Some(keccak256(&body)),
+    };
+    let header = serde_json::to_vec(&header)?;
+    let mut file = Vec::with_capacity(MAGIC.len() + 4 + header.len() + body.len());
+    file.extend_from_slice(MAGIC);
+    file.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    file.extend_from_slice(&header);
+    file.extend_from_slice(&body);
+    Ok(file)
+}
+
+/// Splits a cache file into its header and body, `None` for a cache without a header
+pub(crate) fn split_header(file: &[u8]) -> Result<Option<(CacheHeader, &[u8])>, String> {
+    let Some(rest) = file.strip_prefix(MAGIC) else {
+        return Ok(None);
+    };
+    let (len, rest) = rest
+        .split_first_chunk::<4>()
+        .ok_or("header is truncated")?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err("header is truncated".to_string());
+    }
+    let (header, body) = rest.split_at(len);
+    let header = serde_json::from_slice(header).map_err(|e| e.to_string())?;
+    Ok(Some((header, body)))
+}
+
+/// Why a cache file could not be decoded
+#[derive(Debug)]
+pub(crate) enum DecodeFailure {
+    /// The file was written in a newer format version
+    Unsupported(u32),
+    /// The binary body was written with an older layout of the cache
+    UnsupportedBinary(u32),
+    /// The body does not match the checksum in the header
+    Checksum { expected: B256, actual: B256 },
+    Corrupted(String),
+}
+
+impl<E: std::fmt::Display> From<E> for DecodeFailure {
+    fn from(e: E) -> Self {
+        DecodeFailure::Corrupted(e.to_string())
+    }
+}
+
+/// Decodes a cache file of any supported version
+///
+/// Returns the cache along with the version it was written in, an older cache should be
+/// written back so that it is upgraded in place.
+pub(crate) fn decode(file: &[u8]) -> Result<(PoolCache, u32), DecodeFailure> {
+    let Some((header, body)) = split_header(file)? else {
+        return Ok((migrate_v0(file)?, 0));
+    };
+    if header.format_version > FORMAT_VERSION {
+        return Err(DecodeFailure::Unsupported(header.format_version));
+    }
+    match header.checksum {
+        Some(expected) => {
+            let actual = keccak256(body);
+            if actual != expected {
+                return Err(DecodeFailure::Checksum { expected, actual });
+            }
+        }
+        None if header.format_version >= 2 => {
+            return Err(DecodeFailure::Corrupted("header is missing the checksum".to_string()))
+        }
+        None => {}
+    }
+
+    let body = match header.compression {
+        CacheCompression::None => body.to_vec(),
+        CacheCompression::Gzip => {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(body).read_to_end(&mut decompressed)?;
+            decompressed
+        }
+    };
+    let cache = match header.format_version {
+        // version 2 only added the checksum to the header, the bodies are the same
+        1 | 2 => migrate_v1(&header, &body)?,
+        version => return Err(DecodeFailure::Unsupported(version)),
+    };
+    if cache.pool_type != header.pool_type {
+        return Err(DecodeFailure::Corrupted(format!(
+            "header is for {} but the body holds {}",
+            header.pool_type, cache.pool_type
+        )));
+    }
+    Ok((cache, header.format_version))
+}
+
+/// Version 0 caches are the plain JSON of the cache, fields added since then are defaulted
+fn migrate_v0(file: &[u8]) -> Result<PoolCache, serde_json::Error> {
+    serde_json::from_slice(file)
+}
+
+/// Version 1 and 2 bodies are JSON or bincode of the cache
+fn migrate_v1(header: &CacheHeader, body: &[u8]) -> Result<PoolCache, DecodeFailure> {
+    match header.encoding {
+        CacheEncoding::Json => Ok(serde_json::from_slice(body)?),
+        CacheEncoding::Binary if header.format_version >= BINARY_LAYOUT_VERSION => {
+            Ok(bincode::deserialize(body)?)
+        }
+        CacheEncoding::Binary => Err(DecodeFailure::UnsupportedBinary(header.format_version)),
+    }
+}
+
+fn encoding_error(e: bincode::Error) -> PoolSyncError {
+    PoolSyncError::StoreCorrupted(e.to_string())
+}
@@ -0,0 +1,57 @@
+//! In memory store, nothing outlives the process
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::PoolStore;
+use crate::cache::PoolCache;
+use crate::chain::Chain;
+use crate::errors::PoolSyncError;
+use crate::pools::PoolType;
+use crate::tokens::TokenInfo;
+
+/// Keeps the caches in memory, every sync in a new process starts from scratch
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    caches: Mutex<HashMap<(Chain, PoolType), PoolCache>>,
+    tokens: Mutex<HashMap<Chain, Vec<TokenInfo>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PoolStore for MemoryStore {
+    fn load(&self, chain: Chain, pool_type: PoolType) -> Result<Option<PoolCache>, PoolSyncError> {
+        Ok(self.caches.lock().unwrap().get(&(chain, pool_type)).cloned())
+    }
+
+    fn upsert(&self, chain: Chain, cache: &PoolCache) -> Result<(), PoolSyncError> {
+        let mut stored = cache.clone();
+        stored.dirty.clear();
+        stored.removed.clear();
+        self.caches
+            .lock()
+            .unwrap()
+            .insert((chain, cache.pool_type), stored);
+        Ok(())
+    }
+
+    fn load_tokens(&self, chain: Chain) -> Result<Vec<TokenInfo>, PoolSyncError> {
+        Ok(self
+            .tokens
+            .lock()
+            .unwrap()
+            .get(&chain)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn store_tokens(&self, chain: Chain, tokens: &[&TokenInfo]) -> Result<(), PoolSyncError> {
+        let tokens = tokens.iter().map(|info| (*info).clone()).collect();
+        self.tokens.lock().unwrap().insert(chain, tokens);
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Pool Storage
+//!
+//! This module defines the `PoolStore` trait through which the synced pools, the sync
+//! state of every pool type and the token registry are persisted. A store is chosen on the
+//! builder, the cache files in `cache/` are used by default.
+
+use alloy::primitives::Address;
+
+use crate::cache::PoolCache;
+use crate::chain::Chain;
+use crate::errors::PoolSyncError;
+use crate::pools::{Pool, PoolInfo, PoolType};
+use crate::tokens::TokenInfo;
+
+pub use file::FileStore;
+pub use format::{
+    CacheCompression, CacheEncoding, CacheHeader, BINARY_LAYOUT_VERSION, FORMAT_VERSION,
+};
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+mod file;
+pub(crate) mod format;
+mod memory;
+mod sqlite;
+
+/// Persistence of the pools and sync state of every chain and pool type
+///
+/// A store is shared by all chains, every call names the chain it is for. The store is
+/// called after every sync phase, so it should only write what changed where it can.
+pub trait PoolStore: Send + Sync {
+    /// Reads the cache of the pool type, `None` if it was never stored
+    fn load(&self, chain: Chain, pool_type: PoolType) -> Result<Option<PoolCache>, PoolSyncError>;
+
+    /// Writes the sync state of the cache along with the pools that changed since it was
+    /// last stored
+    ///
+    /// The pools in `cache.dirty` are inserted or replaced and the ones in `cache.removed`
+    /// are deleted. Stores that keep a pool type in a single file may rewrite all pools.
+    fn upsert(&self, chain: Chain, cache: &PoolCache) -> Result<(), PoolSyncError>;
+
+    /// Block the pool type is synced up to, `None` if it was never stored
+    fn last_synced_block(
+        &self,
+        chain: Chain,
+        pool_type: PoolType,
+    ) -> Result<Option<u64>, PoolSyncError> {
+        Ok(self
+            .load(chain, pool_type)?
+            .map(|cache| cache.last_synced_block))
+    }
+
+    /// Whether writes are cheap enough to persist every synced block range
+    ///
+    /// Incremental stores are also written after every sync of a pool type, including each
+    /// block of a live sync. Other stores are only written at the end of a sync.
+    fn incremental(&self) -> bool {
+        false
+    }
+
+    /// Reads the metadata of the tokens of the chain
+    fn load_tokens(&self, chain: Chain) -> Result<Vec<TokenInfo>, PoolSyncError>;
+
+    /// Writes the metadata of the tokens of the chain
+    fn store_tokens(&self, chain: Chain, tokens: &[&TokenInfo]) -> Result<(), PoolSyncError>;
+}
+
+/// Pools of the cache that have to be written, in cache order
+pub(crate) fn dirty_pools(cache: &PoolCache) -> impl Iterator<Item = (Address, &Pool)> {
+    cache
+        .pools
+        .iter()
+        .map(|pool| (pool.address(), pool))
+        .filter(|(address, _)| cache.dirty.contains(address))
+}
@@ -0,0 +1,476 @@
+//! Embedded SQLite store with a normalized, queryable schema
+//!
+//! Pools, their tokens, V3 ticks and bitmap words, Balancer balances, token metadata and
+//! the sync state live in their own tables, so other processes can query pool state with
+//! SQL while the syncer keeps writing. The database runs in WAL mode and every write of a
+//! cache is a single transaction, readers never see a half applied block range.
+
+use alloy::primitives::U256;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use super::{dirty_pools, PoolStore};
+use crate::cache::{PoolCache, SyncCheckpoint};
+use crate::chain::Chain;
+use crate::errors::PoolSyncError;
+use crate::pools::pool_structures::v3_structure::TickInfo;
+use crate::pools::{Pool, PoolInfo, PoolType};
+use crate::tokens::TokenInfo;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS pools (
+        chain TEXT NOT NULL,
+        address TEXT NOT NULL,
+        pool_type TEXT NOT NULL,
+        token0 TEXT NOT NULL,
+        token1 TEXT NOT NULL,
+        fee INTEGER NOT NULL,
+        -- the remaining fields of the pool as json, without ticks, bitmap and balances
+        data TEXT NOT NULL,
+        PRIMARY KEY (chain, address)
+    );
+    CREATE INDEX IF NOT EXISTS pools_by_type ON pools (chain, pool_type);
+
+    CREATE TABLE IF NOT EXISTS pool_tokens (
+        chain TEXT NOT NULL,
+        pool TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        token TEXT NOT NULL,
+        PRIMARY KEY (chain, pool, position)
+    );
+    CREATE INDEX IF NOT EXISTS pool_tokens_by_token ON pool_tokens (chain, token);
+
+    CREATE TABLE IF NOT EXISTS v3_ticks (
+        chain TEXT NOT NULL,
+        pool TEXT NOT NULL,
+        tick INTEGER NOT NULL,
+        liquidity_gross TEXT NOT NULL,
+        liquidity_net TEXT NOT NULL,
+        PRIMARY KEY (chain, pool, tick)
+    );
+
+    CREATE TABLE IF NOT EXISTS v3_bitmap_words (
+        chain TEXT NOT NULL,
+        pool TEXT NOT NULL,
+        word INTEGER NOT NULL,
+        bitmap TEXT NOT NULL,
+        PRIMARY KEY (chain, pool, word)
+    );
+
+    CREATE TABLE IF NOT EXISTS balancer_balances (
+        chain TEXT NOT NULL,
+        pool TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        token TEXT NOT NULL,
+        balance TEXT NOT NULL,
+        PRIMARY KEY (chain, pool, position)
+    );
+
+    CREATE TABLE IF NOT EXISTS tokens (
+        chain TEXT NOT NULL,
+        address TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        name TEXT NOT NULL,
+        decimals INTEGER NOT NULL,
+        total_supply TEXT NOT NULL,
+        supply_block INTEGER NOT NULL,
+        quirks TEXT NOT NULL,
+        PRIMARY KEY (chain, address)
+    );
+
+    CREATE TABLE IF NOT EXISTS sync_state (
+        chain TEXT NOT NULL,
+        pool_type TEXT NOT NULL,
+        last_synced_block INTEGER NOT NULL,
+        is_initial_sync INTEGER NOT NULL,
+        block_window TEXT NOT NULL,
+        journal TEXT NOT NULL,
+        pending_pools TEXT NOT NULL,
+        PRIMARY KEY (chain, pool_type)
+    );
+
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        chain TEXT NOT NULL,
+        pool_type TEXT NOT NULL,
+        end_block INTEGER NOT NULL,
+        phase TEXT NOT NULL,
+        PRIMARY KEY (chain, pool_type)
+    );
+";
+
+/// Tables that hold rows for every pool, keyed by chain and pool address
+const POOL_TABLES: [&str; 5] = [
+    "pools",
+    "pool_tokens",
+    "v3_ticks",
+    "v3_bitmap_words",
+    "balancer_balances",
+];
+
+/// Keeps all chains and pool types in a single SQLite database
+///
+/// Only the changed pools are written, each write is a single transaction.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the database at the path, creating it if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PoolSyncError> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Opens a database that only lives in memory
+    pub fn open_in_memory() -> Result<Self, PoolSyncError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, PoolSyncError> {
+        // readers in other processes do not block the syncer
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl PoolStore for SqliteStore {
+    fn load(&self, chain: Chain, pool_type: PoolType) -> Result<Option<PoolCache>, PoolSyncError> {
+        let conn = self.conn.lock().unwrap();
+        let (chain, pool_type_name) = (chain.to_string(), pool_type.to_string());
+        let state = conn
+            .query_row(
+                "SELECT last_synced_block, is_initial_sync, block_window, journal, pending_pools
+                 FROM sync_state WHERE chain = ?1 AND pool_type = ?2",
+                params![chain, pool_type_name],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, bool>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((last_synced_block, is_initial_sync, block_window, journal, pending_pools)) = state
+        else {
+            return Ok(None);
+        };
+        let checkpoint = conn
+            .query_row(
+                "SELECT end_block, phase FROM checkpoints WHERE chain = ?1 AND pool_type = ?2",
+                params![chain, pool_type_name],
+                |row| Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?
+            .map(|(end_block, phase)| {
+                Ok::<_, PoolSyncError>(SyncCheckpoint {
+                    end_block,
+                    phase: serde_json::from_str(&phase)?,
+                })
+            })
+            .transpose()?;
+
+        let mut pools = Vec::new();
+        let mut statement = conn.prepare(
+            "SELECT address, data FROM pools WHERE chain = ?1 AND pool_type = ?2 ORDER BY rowid",
+        )?;
+        let mut rows = statement.query(params![chain, pool_type_name])?;
+        while let Some(row) = rows.next()? {
+            let address: String = row.get(0)?;
+            let mut pool: Pool = serde_json::from_str(&row.get::<_, String>(1)?)?;
+            load_pool_state(&conn, &chain, &address, &mut pool)?;
+            pools.push(pool);
+        }
+
+        Ok(Some(PoolCache {
+            last_synced_block,
+            pool_type,
+            pools,
+            is_initial_sync,
+            block_window: serde_json::from_str(&block_window)?,
+            journal: serde_json::from_str(&journal)?,
+            pending_pools: serde_json::from_str(&pending_pools)?,
+            checkpoint,
+            dirty: HashSet::new(),
+            removed: HashSet::new(),
+            recovered_from: None,
+        }))
+    }
+
+    fn upsert(&self, chain: Chain, cache: &PoolCache) -> Result<(), PoolSyncError> {
+        let mut conn = self.conn.lock().unwrap();
+        let (chain, pool_type) = (chain.to_string(), cache.pool_type.to_string());
+
+        let tx = conn.transaction()?;
+        for address in &cache.removed {
+            delete_pool(&tx, &chain, &address.to_string())?;
+        }
+        for (address, pool) in dirty_pools(cache) {
+            let address = address.to_string();
+            delete_pool(&tx, &chain, &address)?;
+            insert_pool(&tx, &chain, &pool_type, &address, pool)?;
+        }
+
+        tx.execute(
+            "INSERT INTO sync_state
+                 (chain, pool_type, last_synced_block, is_initial_sync, block_window, journal, pending_pools)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (chain, pool_type) DO UPDATE SET
+                 last_synced_block = excluded.last_synced_block,
+                 is_initial_sync = excluded.is_initial_sync,
+                 block_window = excluded.block_window,
+                 journal = excluded.journal,
+                 pending_pools = excluded.pending_pools",
+            params![
+                chain,
+                pool_type,
+                cache.last_synced_block,
+                cache.is_initial_sync,
+                serde_json::to_string(&cache.block_window)?,
+                serde_json::to_string(&cache.journal)?,
+                serde_json::to_string(&cache.pending_pools)?,
+            ],
+        )?;
+        match &cache.checkpoint {
+            Some(checkpoint) => tx.execute(
+                "INSERT INTO checkpoints (chain, pool_type, end_block, phase) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (chain, pool_type) DO UPDATE
+                 SET end_block = excluded.end_block, phase = excluded.phase",
+                params![
+                    chain,
+                    pool_type,
+                    checkpoint.end_block,
+                    serde_json::to_string(&checkpoint.phase)?
+                ],
+            )?,
+            None => tx.execute(
+                "DELETE FROM checkpoints WHERE chain = ?1 AND pool_type = ?2",
+                params![chain, pool_type],
+            )?,
+        };
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn last_synced_block(
+        &self,
+        chain: Chain,
+        pool_type: PoolType,
+    ) -> Result<Option<u64>, PoolSyncError> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT last_synced_block FROM sync_state WHERE chain = ?1 AND pool_type = ?2",
+                params![chain.to_string(), pool_type.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn incremental(&self) -> bool {
+        true
+    }
+
+    fn load_tokens(&self, chain: Chain) -> Result<Vec<TokenInfo>, PoolSyncError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT address, symbol, name, decimals, total_supply, supply_block, quirks
+             FROM tokens WHERE chain = ?1",
+        )?;
+        let mut rows = statement.query(params![chain.to_string()])?;
+        let mut tokens = Vec::new();
+        while let Some(row) = rows.next()? {
+            tokens.push(TokenInfo {
+                address: parse(&row.get::<_, String>(0)?)?,
+                symbol: row.get(1)?,
+                name: row.get(2)?,
+                decimals: row.get(3)?,
+                total_supply: parse(&row.get::<_, String>(4)?)?,
+                supply_block: row.get(5)?,
+                quirks: serde_json::from_str(&row.get::<_, String>(6)?)?,
+            });
+        }
+        Ok(tokens)
+    }
+
+    fn store_tokens(&self, chain: Chain, tokens: &[&TokenInfo]) -> Result<(), PoolSyncError> {
+        let mut conn = self.conn.lock().unwrap();
+        let chain = chain.to_string();
+        let tx = conn.transaction()?;
+        {
+            let mut upsert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO tokens
+                     (chain, address, symbol, name, decimals, total_supply, supply_block, quirks)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for info in tokens {
+                upsert.execute(params![
+                    chain,
+                    info.address.to_string(),
+                    info.symbol,
+                    info.name,
+                    info.decimals,
+                    info.total_supply.to_string(),
+                    info.supply_block,
+                    serde_json::to_string(&info.quirks)?,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Deletes every row of the pool
+fn delete_pool(tx: &Transaction, chain: &str, address: &str) -> Result<(), PoolSyncError> {
+    for table in POOL_TABLES {
+        let column = if table == "pools" { "address" } else { "pool" };
+        tx.prepare_cached(&format!(
+            "DELETE FROM {table} WHERE chain = ?1 AND {column} = ?2"
+        ))?
+        .execute(params![chain, address])?;
+    }
+    Ok(())
+}
+
+/// Writes the pool into its tables, the large collections get a row per entry
+fn insert_pool(
+    tx: &Transaction,
+    chain: &str,
+    pool_type: &str,
+    address: &str,
+    pool: &Pool,
+) -> Result<(), PoolSyncError> {
+    let mut data = pool.clone();
+    if let Some(pool) = data.get_v3_mut() {
+        pool.ticks.clear();
+        pool.tick_bitmap.clear();
+    } else if let Some(pool) = data.get_balancer_mut() {
+        pool.balances.clear();
+    }
+    tx.prepare_cached(
+        "INSERT INTO pools (chain, address, pool_type, token0, token1, fee, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?
+    .execute(params![
+        chain,
+        address,
+        pool_type,
+        pool.token0_address().to_string(),
+        pool.token1_address().to_string(),
+        pool.fee(),
+        serde_json::to_string(&data)?,
+    ])?;
+
+    let mut insert = tx.prepare_cached(
+        "INSERT INTO pool_tokens (chain, pool, position, token) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (position, token) in pool.token_addresses().iter().enumerate() {
+        insert.execute(params![chain, address, position, token.to_string()])?;
+    }
+
+    if let Some(pool) = pool.get_v3() {
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO v3_ticks (chain, pool, tick, liquidity_gross, liquidity_net)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (tick, info) in &pool.ticks {
+            insert.execute(params![
+                chain,
+                address,
+                tick,
+                info.liquidity_gross.to_string(),
+                info.liquidity_net.to_string()
+            ])?;
+        }
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO v3_bitmap_words (chain, pool, word, bitmap) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (word, bitmap) in &pool.tick_bitmap {
+            insert.execute(params![chain, address, word, bitmap.to_string()])?;
+        }
+    } else if let Some(pool) = pool.get_balancer() {
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO balancer_balances (chain, pool, position, token, balance)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let tokens = pool.get_tokens();
+        for (position, balance) in pool.balances.iter().enumerate() {
+            let token = tokens.get(position).copied().unwrap_or_default();
+            insert.execute(params![
+                chain,
+                address,
+                position,
+                token.to_string(),
+                balance.to_string()
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the ticks, bitmap words or balances of the pool back from their tables
+fn load_pool_state(
+    conn: &Connection,
+    chain: &str,
+    address: &str,
+    pool: &mut Pool,
+) -> Result<(), PoolSyncError> {
+    if let Some(pool) = pool.get_v3_mut() {
+        let mut statement = conn.prepare_cached(
+            "SELECT tick, liquidity_gross, liquidity_net FROM v3_ticks WHERE chain = ?1 AND pool = ?2",
+        )?;
+        let mut rows = statement.query(params![chain, address])?;
+        let mut ticks = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let info = TickInfo {
+                liquidity_gross: parse(&row.get::<_, String>(1)?)?,
+                liquidity_net: parse(&row.get::<_, String>(2)?)?,
+                initialized: true,
+            };
+            ticks.insert(row.get(0)?, info);
+        }
+        pool.ticks = ticks;
+
+        let mut statement = conn.prepare_cached(
+            "SELECT word, bitmap FROM v3_bitmap_words WHERE chain = ?1 AND pool = ?2",
+        )?;
+        let mut rows = statement.query(params![chain, address])?;
+        let mut tick_bitmap = HashMap::new();
+        while let Some(row) = rows.next()? {
+            tick_bitmap.insert(row.get(0)?, parse::<U256>(&row.get::<_, String>(1)?)?);
+        }
+        pool.tick_bitmap = tick_bitmap;
+    } else if let Some(pool) = pool.get_balancer_mut() {
+        let mut statement = conn.prepare_cached(
+            "SELECT balance FROM balancer_balances WHERE chain = ?1 AND pool = ?2 ORDER BY position",
+        )?;
+        let mut rows = statement.query(params![chain, address])?;
+        let mut balances = Vec::new();
+        while let Some(row) = rows.next()? {
+            balances.push(parse(&row.get::<_, String>(0)?)?);
+        }
+        pool.balances = balances;
+    }
+    Ok(())
+}
+
+/// Parses a number or address that is stored as text
+fn parse<T: FromStr>(value: &str) -> Result<T, PoolSyncError>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e: T::Err| PoolSyncError::StoreCorrupted(format!("{value}: {e}")))
+}
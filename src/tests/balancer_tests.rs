@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod balancer_test {
+    use alloy::primitives::{address, Address, LogData, B256, I256, U256};
+    use alloy::rpc::types::Log;
+    use alloy::sol_types::SolEvent;
+
+    use crate::events::BalancerV2Event;
+    use crate::pools::pool_structures::balancer_v2_structure::process_balance_data;
+    use crate::BalancerV2Pool;
+
+    const TOKEN0: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const TOKEN1: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+
+    fn log(data: LogData) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: Address::ZERO,
+                data,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_vault_events() {
+        let mut pool = BalancerV2Pool {
+            token0: TOKEN0,
+            token1: TOKEN1,
+            balances: vec![U256::from(1000), U256::from(1000)],
+            ..Default::default()
+        };
+
+        let swap = BalancerV2Event::Swap {
+            poolId: B256::ZERO,
+            tokenIn: TOKEN0,
+            tokenOut: TOKEN1,
+            amountIn: U256::from(100),
+            amountOut: U256::from(90),
+        };
+        process_balance_data(&mut pool, log(swap.encode_log_data())).unwrap();
+        assert_eq!(pool.balances, vec![U256::from(1100), U256::from(910)]);
+
+        // an exit of token0 and a join of token1 with a protocol fee on token1
+        let join_exit = BalancerV2Event::PoolBalanceChanged {
+            poolId: B256::ZERO,
+            liquidityProvider: Address::ZERO,
+            tokens: vec![TOKEN0, TOKEN1],
+            deltas: vec![I256::try_from(-100).unwrap(), I256::try_from(50).unwrap()],
+            protocolFeeAmounts: vec![U256::ZERO, U256::from(10)],
+        };
+        process_balance_data(&mut pool, log(join_exit.encode_log_data())).unwrap();
+        assert_eq!(pool.balances, vec![U256::from(1000), U256::from(950)]);
+
+        let managed = BalancerV2Event::PoolBalanceManaged {
+            poolId: B256::ZERO,
+            assetManager: Address::ZERO,
+            token: TOKEN0,
+            cashDelta: I256::try_from(-300).unwrap(),
+            managedDelta: I256::try_from(320).unwrap(),
+        };
+        process_balance_data(&mut pool, log(managed.encode_log_data())).unwrap();
+        assert_eq!(pool.balances[0], U256::from(1020));
+
+        let fee = BalancerV2Event::SwapFeePercentageChanged {
+            swapFeePercentage: U256::from(3_000_000_000_000_000u64),
+        };
+        process_balance_data(&mut pool, log(fee.encode_log_data())).unwrap();
+        assert_eq!(pool.swap_fee, U256::from(3_000_000_000_000_000u64));
+    }
+}
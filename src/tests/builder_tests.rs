@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod builder_test {
+    use alloy::providers::Provider;
+    use serde_json::json;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use super::super::mock_rpc::mock::{self, MockRpc, Reply};
+    use crate::endpoints::pool_provider;
+    use crate::builder::PoolSyncBuilder;
+    use crate::{Chain, PoolSync, PoolType};
+
+    /// Chain at block 100 without any pools
+    fn empty_chain() -> MockRpc {
+        MockRpc::new(|method, params| match method {
+            "eth_blockNumber" => Reply::Result(json!("0x64")),
+            "eth_getLogs" => Reply::Result(json!([])),
+            "eth_getBlockByNumber" => {
+                let number = params[0].as_str().unwrap().trim_start_matches("0x");
+                Reply::Result(mock::block(u64::from_str_radix(number, 16).unwrap()))
+            }
+            _ => Reply::Fail("unexpected request"),
+        })
+    }
+
+    /// Syncs uniswap v2 on the mock from block 90, configured by `configure`
+    async fn sync(rpc: &MockRpc, configure: impl FnOnce(PoolSyncBuilder) -> PoolSyncBuilder) -> PoolSync {
+        let builder = PoolSync::builder()
+            .chain(Chain::Ethereum)
+            .add_pool(PoolType::UniswapV2)
+            .start_block(PoolType::UniswapV2, 90)
+            .provider((*mock::provider(rpc)).clone());
+        let pool_sync = configure(builder).build().unwrap();
+        pool_sync.sync_pools().await.unwrap();
+        pool_sync
+    }
+
+    fn last_synced_block(pool_sync: &PoolSync) -> Option<u64> {
+        let store = &pool_sync.chains[&Chain::Ethereum].store;
+        store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().map(|cache| cache.last_synced_block)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pool_sync_builder_{}_{}", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_shared_endpoint_limiter() {
+        // a provider serving both roles is paced by a single bucket
+        let rpc = MockRpc::new(|_, _| Reply::Result(json!("0x64")));
+        let pool_sync = PoolSync::builder()
+            .chain(Chain::Ethereum)
+            .add_pool(PoolType::UniswapV2)
+            .provider((*mock::provider(&rpc)).clone())
+            .rate_limit(2)
+            .persist(false)
+            .build()
+            .unwrap();
+        let chain_sync = &pool_sync.chains[&Chain::Ethereum];
+        let archive = pool_provider(&chain_sync.archive_endpoints);
+        let full = pool_provider(&chain_sync.full_endpoints);
+
+        let start = Instant::now();
+        archive.get_block_number().await.unwrap();
+        archive.get_block_number().await.unwrap();
+        full.get_block_number().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(rpc.calls("eth_blockNumber"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_profiles_share_cache_dir() {
+        // profiles on the same directory keep their caches apart
+        let dir = temp_dir("profiles");
+        let rpc = empty_chain();
+        let a = sync(&rpc, |builder| builder.cache_dir(&dir).profile("a")).await;
+        assert_eq!(last_synced_block(&a), Some(100));
+
+        let b = PoolSync::builder()
+            .chain(Chain::Ethereum)
+            .add_pool(PoolType::UniswapV2)
+            .provider((*mock::provider(&rpc)).clone())
+            .cache_dir(&dir)
+            .profile("b")
+            .build()
+            .unwrap();
+        assert_eq!(last_synced_block(&b), None);
+        assert!(dir.join("a").join("Ethereum_UniswapV2.cache").exists());
+        assert!(!dir.join("b").join("Ethereum_UniswapV2.cache").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_without_persistence() {
+        // nothing is written to the cache directory, the next build starts from scratch
+        let dir = temp_dir("memory");
+        let rpc = empty_chain();
+        let pool_sync = sync(&rpc, |builder| builder.cache_dir(&dir).persist(false)).await;
+        assert_eq!(last_synced_block(&pool_sync), Some(100));
+        assert!(!dir.exists());
+
+        let pool_sync = PoolSync::builder()
+            .chain(Chain::Ethereum)
+            .add_pool(PoolType::UniswapV2)
+            .provider((*mock::provider(&rpc)).clone())
+            .cache_dir(&dir)
+            .persist(false)
+            .build()
+            .unwrap();
+        assert_eq!(last_synced_block(&pool_sync), None);
+    }
+}
@@ -29,7 +29,9 @@ mod data_test {
             .chain(Chain::Base)
             .rate_limit(1000)
             .build().unwrap();
-        let (pools, last_synced_block) = pool_sync.sync_pools().await.unwrap();
+        let report = pool_sync.sync_pools().await.unwrap().chains.remove(&Chain::Base).unwrap();
+        let last_synced_block = report.last_synced_block();
+        let pools = report.pools;
 
         let provider = Arc::new(ProviderBuilder::new()
             .on_http(std::env::var("FULL").unwrap().parse().unwrap()));
@@ -65,7 +67,9 @@ mod data_test {
             .chain(Chain::Base)
             .rate_limit(1000)
             .build().unwrap();
-        let (pools, last_synced_block) = pool_sync.sync_pools().await.unwrap();
+        let report = pool_sync.sync_pools().await.unwrap().chains.remove(&Chain::Base).unwrap();
+        let last_synced_block = report.last_synced_block();
+        let pools = report.pools;
         let provider = Arc::new(ProviderBuilder::new()
             .on_http(std::env::var("FULL").unwrap().parse().unwrap()));
 
@@ -102,8 +106,8 @@ mod data_test {
                         .await
                         .unwrap();
                     
-                    assert_eq!(liquidityGross as u128, tick_val.liquidity_gross as u128, "Liquidity Gross at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
-                    assert_eq!(liquidityNet as i128, tick_val.liquidity_net as i128, "Liquidity Net at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
+                    assert_eq!(liquidityGross, tick_val.liquidity_gross, "Liquidity Gross at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
+                    assert_eq!(liquidityNet, tick_val.liquidity_net, "Liquidity Net at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
                 }
 
                 (sqrtPriceX96, tick, liquidity, tick_spacing, fee)
@@ -124,8 +128,8 @@ mod data_test {
                         .await
                         .unwrap();
                     
-                    assert_eq!(liquidityGross as u128, tick_val.liquidity_gross as u128, "Liquidity Gross at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
-                    assert_eq!(liquidityNet as i128, tick_val.liquidity_net as i128, "Liquidity Net at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
+                    assert_eq!(liquidityGross, tick_val.liquidity_gross, "Liquidity Gross at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
+                    assert_eq!(liquidityNet, tick_val.liquidity_net, "Liquidity Net at tick {}: Address {}, Pool Type {}", tick_key, pool.address, pool_type);
                 }
 
                 (sqrtPriceX96, tick, liquidity, tick_spacing, fee)
@@ -135,7 +139,7 @@ mod data_test {
         // Assert common values outside the match
         assert_eq!(pool.sqrt_price, U256::from(sqrt_price), "SqrtPrice: Address {}, Pool Type {}", pool.address, pool_type);
         assert_eq!(pool.tick, tick.as_i32(), "Tick: Address {}, Pool Type {}", pool.address, pool_type);
-        assert_eq!(pool.liquidity, liquidity as u128, "Liquidity: Address {}, Pool Type {}", pool.address, pool_type);
+        assert_eq!(pool.liquidity, liquidity, "Liquidity: Address {}, Pool Type {}", pool.address, pool_type);
         assert_eq!(pool.tick_spacing, tick_spacing.as_i32(), "Tick spacing: Address {}, Pool Type {}", pool.address, pool_type);
         assert_eq!(pool.fee, fee.to::<u32>(), "Fee: Address {}, Pool Type {}", pool.address, pool_type);
     }
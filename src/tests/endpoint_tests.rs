@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod endpoint_test {
+    use alloy::providers::Provider;
+    use alloy::rpc::types::Filter;
+    use alloy::transports::Transport;
+    use indicatif::ProgressBar;
+    use std::sync::Arc;
+
+    use super::super::mock_rpc::mock::{self, MockRpc, Reply};
+    use crate::endpoints::{pool_provider, EndpointPool};
+    use crate::rpc::RpcBudget;
+    use crate::Rpc;
+
+    /// Endpoint that rejects get_logs ranges larger than `max_range` blocks
+    fn range_limited(max_range: u64) -> MockRpc {
+        MockRpc::new(move |method, params| {
+            assert_eq!(method, "eth_getLogs");
+            let to_block = params[0]["toBlock"].as_str().unwrap();
+            let to_block = u64::from_str_radix(to_block.trim_start_matches("0x"), 16).unwrap();
+            if to_block - mock::from_block(params) + 1 > max_range {
+                Reply::Error(-32005, "eth_getLogs is limited to a 250 range")
+            } else {
+                Reply::Result(serde_json::json!([]))
+            }
+        })
+    }
+
+    /// Endpoint at the given height that answers eth_chainId with a revert
+    fn endpoint(head: &'static str) -> MockRpc {
+        MockRpc::new(move |method, _| match method {
+            "eth_blockNumber" => Reply::Result(serde_json::json!(head)),
+            "eth_chainId" => Reply::Error(3, "execution reverted"),
+            _ => Reply::Fail("unexpected request"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_error_payload_fails_over() {
+        let limited = MockRpc::new(|_, _| Reply::Error(-32005, "rate limit exceeded"));
+        let healthy = endpoint("0x64");
+        let pool = EndpointPool::new(vec![(limited.clone().boxed(), 1), (healthy.clone().boxed(), 1)]);
+        let provider = pool_provider(&pool);
+
+        for _ in 0..4 {
+            assert_eq!(provider.get_block_number().await.unwrap(), 100);
+        }
+        assert!(limited.calls("eth_blockNumber") > 0);
+        assert_eq!(healthy.calls("eth_blockNumber"), 4);
+        assert!(pool.health()[0].success_rate < 1.0);
+        assert_eq!(pool.health()[1].consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_result_limit_is_returned() {
+        // a get_logs range that is too large is split by the caller, not failed over
+        let limited = MockRpc::new(|_, _| {
+            Reply::Error(-32005, "query returned more than 10000 results")
+        });
+        let other = endpoint("0x64");
+        let pool = EndpointPool::new(vec![(limited.clone().boxed(), 3), (other.clone().boxed(), 1)]);
+        let provider = pool_provider(&pool.pinned());
+
+        for _ in 0..4 {
+            let error = provider.get_logs(&Filter::new()).await.unwrap_err();
+            assert!(error.to_string().contains("more than 10000 results"));
+        }
+        assert_eq!(limited.calls("eth_getLogs"), 4);
+        assert_eq!(other.calls("eth_getLogs"), 0);
+        assert_eq!(pool.health()[0].consecutive_failures, 0);
+        assert!(pool.health()[0].cooldown_until.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_endpoint() {
+        let (first, second) = (endpoint("0x64"), endpoint("0x65"));
+        let pool = EndpointPool::new(vec![(first.clone().boxed(), 3), (second.clone().boxed(), 1)]);
+        let provider = pool_provider(&pool.pinned());
+
+        for _ in 0..4 {
+            assert_eq!(provider.get_block_number().await.unwrap(), 100);
+        }
+        assert_eq!(second.calls("eth_blockNumber"), 0);
+
+        // a revert is an answer of the endpoint, it is not retried on another one
+        assert!(provider.get_chain_id().await.is_err());
+        assert_eq!(first.calls("eth_chainId"), 1);
+        assert_eq!(second.calls("eth_chainId"), 0);
+        assert_eq!(pool.health()[0].consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_log_range_cap_persists() {
+        let limited = range_limited(250);
+        let pool = EndpointPool::new(vec![(limited.clone().boxed(), 1)]);
+        let fetch = |pool: EndpointPool| {
+            Rpc::fetch_event_logs(
+                0,
+                999,
+                1000,
+                Arc::new(pool_provider(&pool)),
+                RpcBudget::new(1).with_log_endpoints(pool),
+                Arc::new(ProgressBar::hidden()),
+                vec![Filter::new()],
+                |_| Ok(()),
+            )
+        };
+
+        // the first pass bisects 1000 into 500 and 250 blocks, 1 + 2 + 4 requests
+        fetch(pool.clone()).await.unwrap();
+        assert_eq!(limited.calls("eth_getLogs"), 7);
+        assert_eq!(pool.health()[0].log_range_cap, Some(250));
+
+        // a later pass with a new budget starts at the learned range
+        fetch(pool.clone()).await.unwrap();
+        assert_eq!(limited.calls("eth_getLogs"), 11);
+    }
+
+    #[tokio::test]
+    async fn test_log_range_cap_per_endpoint() {
+        let limited = range_limited(250);
+        let unlimited = range_limited(u64::MAX);
+        let pool = EndpointPool::new(vec![(limited.clone().boxed(), 3), (unlimited.clone().boxed(), 1)]);
+        let provider = pool_provider(&pool);
+        let filter = Filter::new().from_block(0).to_block(999);
+
+        // the rejection is returned to the caller and only limits the endpoint that sent it
+        assert!(provider.get_logs(&filter).await.is_err());
+        assert_eq!(pool.health()[0].log_range_cap, Some(500));
+        assert_eq!(pool.health()[1].log_range_cap, None);
+        assert_eq!(pool.log_range_cap(), u64::MAX);
+
+        // large ranges go to the endpoint that accepts them, small ones to both
+        for _ in 0..4 {
+            provider.get_logs(&filter).await.unwrap();
+        }
+        assert_eq!(limited.calls("eth_getLogs"), 1);
+        assert_eq!(unlimited.calls("eth_getLogs"), 4);
+        let small = Filter::new().from_block(0).to_block(99);
+        for _ in 0..4 {
+            provider.get_logs(&small).await.unwrap();
+        }
+        assert!(limited.calls("eth_getLogs") > 1);
+    }
+}
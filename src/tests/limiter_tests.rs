@@ -0,0 +1,19 @@
+#[cfg(test)]
+mod limiter_test {
+    use std::time::Duration;
+
+    use crate::limiter::refill;
+
+    #[test]
+    fn test_refill() {
+        // rates that are not a multiple of 1000 keep their remainder
+        assert_eq!(refill(1999), (1999, Duration::from_secs(1)));
+        assert_eq!(refill(2500), (5, Duration::from_millis(2)));
+        assert_eq!(refill(4), (1, Duration::from_millis(250)));
+
+        for per_second in [1001, 1999, 2500, 10_000, 12_345] {
+            let (amount, interval) = refill(per_second);
+            assert_eq!(amount as f64 / interval.as_secs_f64(), per_second as f64);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod log_order_test {
+    use alloy::primitives::aliases::{I24, U160};
+    use alloy::primitives::{Address, LogData, I256, U256};
+    use alloy::rpc::types::Log;
+    use alloy::sol_types::SolEvent;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    use crate::events::DataEvents;
+    use crate::pools::pool_structures::v3_structure::process_tick_data;
+    use crate::rpc::sort_logs;
+    use crate::{PoolType, UniswapV3Pool};
+
+    fn tick(tick: i32) -> I24 {
+        I24::try_from(tick).unwrap()
+    }
+
+    fn log(index: u64, data: LogData) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: Address::ZERO,
+                data,
+            },
+            block_number: Some(100),
+            transaction_index: Some(index / 2),
+            log_index: Some(index),
+            ..Default::default()
+        }
+    }
+
+    fn mint(index: u64, lower: i32, upper: i32, amount: u128) -> Log {
+        let mint = DataEvents::Mint {
+            sender: Address::ZERO,
+            owner: Address::ZERO,
+            tickLower: tick(lower),
+            tickUpper: tick(upper),
+            amount,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        };
+        log(index, mint.encode_log_data())
+    }
+
+    fn burn(index: u64, lower: i32, upper: i32, amount: u128) -> Log {
+        let burn = DataEvents::Burn {
+            owner: Address::ZERO,
+            tickLower: tick(lower),
+            tickUpper: tick(upper),
+            amount,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        };
+        log(index, burn.encode_log_data())
+    }
+
+    fn swap(index: u64, to_tick: i32, sqrt_price: u64, liquidity: u128) -> Log {
+        let swap = DataEvents::Swap {
+            sender: Address::ZERO,
+            recipient: Address::ZERO,
+            amount0: I256::ZERO,
+            amount1: I256::ZERO,
+            sqrtPriceX96: U160::from(sqrt_price),
+            liquidity,
+            tick: tick(to_tick),
+        };
+        log(index, swap.encode_log_data())
+    }
+
+    fn replay(logs: Vec<Log>) -> UniswapV3Pool {
+        let mut pool = UniswapV3Pool {
+            liquidity: 1000,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+        for log in logs {
+            process_tick_data(&mut pool, log, PoolType::UniswapV3, false).unwrap();
+        }
+        pool
+    }
+
+    #[test]
+    fn test_shuffled_block_replay() {
+        // mints and burns move the active liquidity, swaps overwrite it
+        let block = vec![
+            mint(0, -60, 60, 500),
+            swap(1, 5, 1 << 40, 1500),
+            burn(2, -60, 60, 200),
+            swap(3, 70, 1 << 41, 1000),
+            mint(4, 0, 120, 100),
+            mint(5, -120, 60, 50),
+        ];
+        let expected = replay(block.clone());
+        assert_eq!(expected.liquidity, 1100);
+        assert_eq!(expected.tick, 70);
+        assert_eq!(expected.sqrt_price, U256::from(1u64 << 41));
+
+        let expected = serde_json::to_value(&expected).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..50 {
+            let mut logs = block.clone();
+            logs.shuffle(&mut rng);
+            sort_logs(&mut logs);
+            assert_eq!(serde_json::to_value(replay(logs)).unwrap(), expected);
+        }
+    }
+}
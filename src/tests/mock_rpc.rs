@@ -0,0 +1,159 @@
+#[cfg(test)]
+pub(crate) mod mock {
+    use alloy::primitives::{Address, Bytes, LogData, B256};
+    use alloy::rpc::json_rpc::{
+        ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload, SerializedRequest,
+    };
+    use alloy::rpc::types::{Block, Log};
+    use alloy::transports::{Transport, TransportError, TransportErrorKind, TransportFut};
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use tower::Service;
+
+    use crate::chain::Chain;
+    use crate::endpoints::{pool_provider, EndpointPool};
+    use crate::pools::pool_fetchers::UniswapV2Fetcher;
+    use crate::pools::{PoolFetcher, PoolType};
+    use crate::store::PoolStore;
+    use crate::{ChainSync, SyncProvider};
+
+    /// Answer of the mock to a single request
+    pub(crate) enum Reply {
+        Result(Value),
+        /// Json rpc error payload with its code and message
+        Error(i64, &'static str),
+        /// The request fails on the transport level
+        Fail(&'static str),
+    }
+
+    type Handler = dyn Fn(&str, &Value) -> Reply + Send + Sync;
+
+    /// Transport that answers every request with the handler, counting the requests by method
+    #[derive(Clone)]
+    pub(crate) struct MockRpc {
+        handler: Arc<Handler>,
+        calls: Arc<Mutex<HashMap<String, usize>>>,
+    }
+
+    impl MockRpc {
+        pub(crate) fn new(handler: impl Fn(&str, &Value) -> Reply + Send + Sync + 'static) -> Self {
+            Self {
+                handler: Arc::new(handler),
+                calls: Arc::default(),
+            }
+        }
+
+        /// Number of requests of the method the mock received
+        pub(crate) fn calls(&self, method: &str) -> usize {
+            self.calls.lock().unwrap().get(method).copied().unwrap_or_default()
+        }
+
+        fn answer(&self, request: &SerializedRequest) -> Result<Response, TransportError> {
+            *self
+                .calls
+                .lock()
+                .unwrap()
+                .entry(request.method().to_string())
+                .or_default() += 1;
+            let params = request
+                .params()
+                .map(|params| serde_json::from_str(params.get()).unwrap())
+                .unwrap_or(Value::Null);
+            let payload = match (self.handler)(request.method(), &params) {
+                Reply::Result(value) => {
+                    ResponsePayload::Success(serde_json::value::to_raw_value(&value).unwrap())
+                }
+                Reply::Error(code, message) => ResponsePayload::Failure(ErrorPayload {
+                    code,
+                    message: message.into(),
+                    data: None,
+                }),
+                Reply::Fail(message) => return Err(TransportErrorKind::custom_str(message)),
+            };
+            Ok(Response {
+                id: request.id().clone(),
+                payload,
+            })
+        }
+    }
+
+    impl Service<RequestPacket> for MockRpc {
+        type Response = ResponsePacket;
+        type Error = TransportError;
+        type Future = TransportFut<'static>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: RequestPacket) -> Self::Future {
+            let response = match &request {
+                RequestPacket::Single(request) => self.answer(request).map(ResponsePacket::Single),
+                RequestPacket::Batch(requests) => requests
+                    .iter()
+                    .map(|request| self.answer(request))
+                    .collect::<Result<_, _>>()
+                    .map(ResponsePacket::Batch),
+            };
+            Box::pin(async move { response })
+        }
+    }
+
+    /// Chain sync of uniswap v2 pools on ethereum with every provider backed by the mock
+    pub(crate) fn chain_sync(rpc: &MockRpc, store: Arc<dyn PoolStore>) -> ChainSync {
+        let endpoints = EndpointPool::new(vec![(rpc.clone().boxed(), 1)]);
+        let fetcher: Arc<dyn PoolFetcher> = Arc::new(UniswapV2Fetcher);
+        ChainSync {
+            chain: Chain::Ethereum,
+            fetchers: HashMap::from([(PoolType::UniswapV2, fetcher)]),
+            start_blocks: HashMap::new(),
+            archive: Arc::new(pool_provider(&endpoints)),
+            archive_endpoints: endpoints.clone(),
+            full_endpoints: endpoints,
+            tokens: Arc::default(),
+            store,
+        }
+    }
+
+    /// Provider backed by the mock
+    pub(crate) fn provider(rpc: &MockRpc) -> Arc<SyncProvider> {
+        Arc::new(pool_provider(&EndpointPool::new(vec![(rpc.clone().boxed(), 1)])))
+    }
+
+    /// Block with the given number, its hash is derived from the number
+    pub(crate) fn block(number: u64) -> Value {
+        let mut block = Block::<B256>::default();
+        block.header.inner.number = number;
+        block.header.hash = B256::with_last_byte(number as u8);
+        serde_json::to_value(block).unwrap()
+    }
+
+    /// Log emitted by the address in the block
+    pub(crate) fn log(address: Address, data: LogData, block_number: u64) -> Value {
+        let log = Log {
+            inner: alloy::primitives::Log { address, data },
+            block_number: Some(block_number),
+            block_hash: Some(B256::with_last_byte(block_number as u8)),
+            transaction_index: Some(0),
+            log_index: Some(0),
+            ..Default::default()
+        };
+        serde_json::to_value(log).unwrap()
+    }
+
+    /// Target and calldata of an eth_call request, no target for a deploy call
+    pub(crate) fn call(params: &Value) -> (Option<Address>, Bytes) {
+        let request = &params[0];
+        let to = request["to"].as_str().map(|to| to.parse().unwrap());
+        let input = request["input"].as_str().or(request["data"].as_str()).unwrap();
+        (to, input.parse().unwrap())
+    }
+
+    /// First block of the range of an eth_getLogs request
+    pub(crate) fn from_block(params: &Value) -> u64 {
+        let from_block = params[0]["fromBlock"].as_str().unwrap();
+        u64::from_str_radix(from_block.trim_start_matches("0x"), 16).unwrap()
+    }
+}
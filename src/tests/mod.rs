@@ -1,2 +1,14 @@
 mod abi_gen;
-mod data_tests;
\ No newline at end of file
+mod balancer_tests;
+mod builder_tests;
+mod data_tests;
+mod endpoint_tests;
+mod limiter_tests;
+mod log_order_tests;
+mod mock_rpc;
+mod reorg_tests;
+mod rpc_tests;
+mod snapshot_tests;
+mod store_tests;
+mod sync_tests;
+mod token_tests;
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod reorg_test {
+    use alloy::primitives::aliases::{I24, U112};
+    use alloy::primitives::{address, Address, LogData, B256, U256};
+    use alloy::rpc::types::Log;
+    use alloy::sol_types::SolEvent;
+
+    use crate::events::DataEvents;
+    use crate::pools::pool_structures::v2_structure::process_sync_data;
+    use crate::pools::pool_structures::v3_structure::process_tick_data;
+    use crate::reorg::{BlockWindow, UndoJournal, REORG_WINDOW};
+    use crate::{Pool, PoolType, UniswapV2Pool, UniswapV3Pool};
+
+    const POOL: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+
+    fn log(data: LogData, block_number: u64) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: POOL,
+                data,
+            },
+            block_number: Some(block_number),
+            ..Default::default()
+        }
+    }
+
+    fn sync_log(reserve0: u64, reserve1: u64, block_number: u64) -> Log {
+        let event = DataEvents::Sync {
+            reserve0: U112::from(reserve0),
+            reserve1: U112::from(reserve1),
+        };
+        log(event.encode_log_data(), block_number)
+    }
+
+    fn mint_log(tick_lower: i32, tick_upper: i32, amount: u128, block_number: u64) -> Log {
+        let event = DataEvents::Mint {
+            sender: Address::ZERO,
+            owner: Address::ZERO,
+            tickLower: I24::try_from(tick_lower).unwrap(),
+            tickUpper: I24::try_from(tick_upper).unwrap(),
+            amount,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        };
+        log(event.encode_log_data(), block_number)
+    }
+
+    #[test]
+    fn test_v2_rollback() {
+        let mut pools = vec![Pool::UniswapV2(UniswapV2Pool {
+            address: POOL,
+            token0_reserves: U256::from(100),
+            token1_reserves: U256::from(200),
+            ..Default::default()
+        })];
+        let mut journal = UndoJournal::default();
+
+        for (block, log) in [(11, sync_log(110, 190, 11)), (12, sync_log(120, 180, 12))] {
+            journal.record(block, &pools[0], &log);
+            process_sync_data(pools[0].get_v2_mut().unwrap(), log, PoolType::UniswapV2).unwrap();
+        }
+
+        journal.rollback(&mut pools, 11);
+        let pool = pools[0].get_v2().unwrap();
+        assert_eq!(pool.token0_reserves, U256::from(110));
+        assert_eq!(pool.token1_reserves, U256::from(190));
+
+        journal.rollback(&mut pools, 10);
+        let pool = pools[0].get_v2().unwrap();
+        assert_eq!(pool.token0_reserves, U256::from(100));
+        assert_eq!(pool.token1_reserves, U256::from(200));
+    }
+
+    #[test]
+    fn test_v3_rollback() {
+        let mut pools = vec![Pool::UniswapV3(UniswapV3Pool {
+            address: POOL,
+            tick_spacing: 60,
+            liquidity: 1000,
+            ..Default::default()
+        })];
+        let mut journal = UndoJournal::default();
+
+        let log = mint_log(-120, 120, 500, 11);
+        journal.record(11, &pools[0], &log);
+        process_tick_data(pools[0].get_v3_mut().unwrap(), log, PoolType::UniswapV3, false).unwrap();
+        journal.record_created(11, address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640"));
+        pools.push(Pool::UniswapV3(UniswapV3Pool {
+            address: address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640"),
+            ..Default::default()
+        }));
+
+        let pool = pools[0].get_v3().unwrap();
+        assert_eq!(pool.liquidity, 1500);
+        assert_eq!(pool.ticks.len(), 2);
+
+        journal.rollback(&mut pools, 10);
+        assert_eq!(pools.len(), 1);
+        let pool = pools[0].get_v3().unwrap();
+        assert_eq!(pool.liquidity, 1000);
+        assert!(pool.ticks.is_empty());
+        assert!(pool.tick_bitmap.is_empty());
+    }
+
+    #[test]
+    fn test_block_window() {
+        let mut window = BlockWindow::default();
+        for number in 0..=REORG_WINDOW * 2 {
+            window.record(number, B256::with_last_byte(number as u8));
+        }
+        assert_eq!(window.iter_newest().count() as u64, REORG_WINDOW + 1);
+
+        // recording an earlier block replaces everything after it
+        window.record(REORG_WINDOW, B256::ZERO);
+        assert_eq!(window.iter_newest().next(), Some(&(REORG_WINDOW, B256::ZERO)));
+    }
+}
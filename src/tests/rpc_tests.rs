@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod rpc_test {
+    use alloy::dyn_abi::DynSolValue;
+    use alloy::primitives::{Address, Bytes, U256};
+    use alloy::sol_types::SolValue;
+    use alloy::rpc::types::Filter;
+    use indicatif::ProgressBar;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    use super::super::mock_rpc::mock::{self, MockRpc, Reply};
+    use crate::pools::pool_fetchers::{AerodromeFetcher, BALANCER_V2_VAULT};
+    use crate::rpc::{log_limit, LogLimit, LogScope, RpcBudget};
+    use crate::{Chain, Pool, PoolType, Rpc, UniswapV2Pool};
+
+    fn pools(count: usize) -> Vec<Pool> {
+        (0..count)
+            .map(|i| {
+                Pool::UniswapV2(UniswapV2Pool {
+                    address: Address::with_last_byte(i as u8),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_log_scope() {
+        // a few pools over a long range are cheaper to filter by address
+        let scope = Rpc::get_log_scope(PoolType::UniswapV2, &pools(10), 0, 1_000_000, 250);
+        assert!(matches!(scope, LogScope::Addresses { ref chunks, step_size: 2500 } if chunks.len() == 1));
+
+        // a range shorter than a step costs a request per chunk against a single global one
+        let scope = Rpc::get_log_scope(PoolType::UniswapV2, &pools(2000), 0, 999, 250);
+        assert!(matches!(scope, LogScope::Addresses { ref chunks, .. } if chunks.len() == 2));
+        let scope = Rpc::get_log_scope(PoolType::UniswapV2, &pools(2000), 0, 249, 250);
+        assert_eq!(scope, LogScope::Global);
+
+        // a single block is one global request
+        let scope = Rpc::get_log_scope(PoolType::UniswapV2, &pools(2000), 100, 100, 250);
+        assert_eq!(scope, LogScope::Global);
+
+        // balancer balance changes always come from the vault
+        let scope = Rpc::get_log_scope(PoolType::BalancerV2, &[], 100, 100, 5000);
+        assert_eq!(
+            scope,
+            LogScope::Addresses {
+                chunks: vec![vec![BALANCER_V2_VAULT]],
+                step_size: 5000
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_filters_are_counted() {
+        // every address filter is a request of its own, each one holds a permit
+        let rpc = MockRpc::new(|method, _| {
+            assert_eq!(method, "eth_getLogs");
+            Reply::Result(json!([]))
+        });
+        let filters = vec![
+            Filter::new().address(Address::with_last_byte(1)),
+            Filter::new().address(Address::with_last_byte(2)),
+        ];
+        let budget = RpcBudget::new(1);
+        Rpc::fetch_event_logs(
+            0,
+            99,
+            50,
+            mock::provider(&rpc),
+            budget.clone(),
+            Arc::new(ProgressBar::hidden()),
+            filters,
+            |_| Ok(()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rpc.calls("eth_getLogs"), 4);
+        assert_eq!(budget.calls(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_aerodrome_reads_are_counted() {
+        // the stable flag and the fee are read with a permit each after the DataSync call
+        let pool = Address::with_last_byte(1);
+        let rpc = MockRpc::new(move |method, params| {
+            assert_eq!(method, "eth_call");
+            let output: Bytes = match mock::call(params) {
+                (None, _) => DynSolValue::Array(vec![DynSolValue::Tuple(vec![
+                    DynSolValue::Address(pool),
+                    DynSolValue::Address(Address::with_last_byte(2)),
+                    DynSolValue::Address(Address::with_last_byte(3)),
+                    DynSolValue::Uint(U256::from(18), 8),
+                    DynSolValue::Uint(U256::from(18), 8),
+                    DynSolValue::Uint(U256::from(100), 256),
+                    DynSolValue::Uint(U256::from(200), 256),
+                ])])
+                .abi_encode()
+                .into(),
+                (Some(to), _) if to == pool => true.abi_encode().into(),
+                (Some(_), _) => U256::from(5).abi_encode().into(),
+            };
+            Reply::Result(json!(output))
+        });
+        let budget = RpcBudget::new(1);
+        let (pools, failed) = Rpc::populate_pools(
+            vec![pool],
+            mock::provider(&rpc),
+            PoolType::Aerodrome,
+            Arc::new(AerodromeFetcher),
+            budget.clone(),
+            Chain::Base,
+        )
+        .await
+        .unwrap();
+        assert!(failed.is_empty());
+        let pool = pools[0].get_v2().unwrap();
+        assert_eq!((pool.stable, pool.fee), (Some(true), Some(U256::from(5))));
+        assert_eq!(budget.calls(), 3);
+        assert_eq!(rpc.calls("eth_call"), 3);
+    }
+
+    #[test]
+    fn test_log_limit() {
+        assert_eq!(
+            log_limit("query returned more than 10000 results"),
+            Some(LogLimit::Results)
+        );
+        assert_eq!(
+            log_limit("Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range"),
+            Some(LogLimit::Results)
+        );
+        assert_eq!(
+            log_limit("eth_getLogs is limited to a 10000 range"),
+            Some(LogLimit::Range)
+        );
+        assert_eq!(log_limit("connection reset by peer"), None);
+        assert_eq!(log_limit("rate limit exceeded"), None);
+        assert_eq!(log_limit("Too Many Requests"), None);
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod snapshot_test {
+    use alloy::primitives::aliases::{I24, U160};
+    use alloy::primitives::{address, Address, Bytes, U256};
+    use alloy::sol_types::{SolCall, SolValue};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::super::mock_rpc::mock::{self, MockRpc, Reply};
+    use crate::multicall::{IMulticall3, MULTICALL3};
+    use crate::pools::pool_structures::v3_structure::update_position;
+    use crate::pools::snapshot::{
+        decode_walk, snapshot_v3_pools, tick_bitmap, IV3TickState, TickData, MIN_TICK,
+    };
+    use crate::rpc::RpcBudget;
+    use crate::{Pool, UniswapV3Pool};
+
+    const POOL: Address = address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640");
+
+    fn step(initialized: bool, tick: i32, liquidity_net: i128) -> TickData {
+        TickData {
+            initialized,
+            tick: I24::try_from(tick).unwrap(),
+            liquidityNet: liquidity_net,
+        }
+    }
+
+    fn walk(steps: Vec<TickData>) -> Bytes {
+        (steps, U256::from(100)).abi_encode_params().into()
+    }
+
+    #[test]
+    fn test_decode_walk() {
+        // word edges are skipped, the walk stops at the end marker
+        let data = walk(vec![
+            step(false, 0, 0),
+            step(true, -120, 100),
+            step(false, MIN_TICK, 0),
+            step(false, 0, 0),
+        ]);
+        assert_eq!(decode_walk(&data).unwrap(), (vec![-120], None));
+
+        // a walk without the marker continues from its last step
+        let data = walk(vec![step(true, 60, -100), step(false, 15300, 0)]);
+        assert_eq!(decode_walk(&data).unwrap(), (vec![60], Some(15300)));
+    }
+
+    #[test]
+    fn test_tick_bitmap() {
+        // the bitmap rebuilt from the ticks is the one opening positions on them produces
+        let mut pool = UniswapV3Pool {
+            tick_spacing: 60,
+            ..Default::default()
+        };
+        update_position(&mut pool, -887220, -60, 100);
+        update_position(&mut pool, 0, 180, 50);
+        update_position(&mut pool, 15360, 887220, 10);
+
+        let ticks = [-887220, -60, 0, 180, 15360, 887220];
+        assert_eq!(tick_bitmap(ticks, 60), pool.tick_bitmap);
+    }
+
+    /// Answers the reads of a pool at tick 0 with positions on ticks -120 and 60
+    fn answer(call: &IMulticall3::Call3) -> Vec<u8> {
+        let data = &call.callData;
+        if let Ok(call) = IV3TickState::ticksCall::abi_decode(data, true) {
+            let net: i128 = if call.tick.as_i32() < 0 { 100 } else { -100 };
+            (100u128, net).abi_encode_params()
+        } else if data[..] == IV3TickState::slot0Call::SELECTOR {
+            (U160::from(1u128 << 96), I24::ZERO).abi_encode_params()
+        } else {
+            100u128.abi_encode()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_v3_pools() {
+        let tick_reads = Arc::new(AtomicUsize::new(0));
+        let reads = tick_reads.clone();
+        let rpc = MockRpc::new(move |method, params| {
+            assert_eq!(method, "eth_call");
+            let (to, input) = mock::call(params);
+            let output: Bytes = match to {
+                Some(to) => {
+                    assert_eq!(to, MULTICALL3);
+                    let calls = IMulticall3::aggregate3Call::abi_decode(&input, true).unwrap().calls;
+                    let ticks = calls.iter().filter(|call| call.callData[..4] == IV3TickState::ticksCall::SELECTOR);
+                    reads.fetch_add(ticks.count(), Ordering::SeqCst);
+                    let results: Vec<IMulticall3::CallResult> = calls
+                        .iter()
+                        .map(|call| IMulticall3::CallResult {
+                            success: true,
+                            returnData: answer(call).into(),
+                        })
+                        .collect();
+                    results.abi_encode().into()
+                }
+                // the walker takes its direction and start tick as the second and third
+                // constructor arguments, the continued up walk returns its start again
+                None => match (input[input.len() - 3 * 32 - 1], input[input.len() - 2 * 32 - 1]) {
+                    (1, _) => walk(vec![step(false, 0, 0), step(true, -120, 100), step(false, MIN_TICK, 0)]),
+                    (_, 0) => walk(vec![step(true, 60, -100)]),
+                    _ => walk(vec![step(true, 60, -100), step(false, MIN_TICK, 0)]),
+                },
+            };
+            Reply::Result(json!(output))
+        });
+
+        let mut pools = vec![Pool::UniswapV3(UniswapV3Pool {
+            address: POOL,
+            tick_spacing: 60,
+            ..Default::default()
+        })];
+        snapshot_v3_pools(&mock::provider(&rpc), &mut pools, 100, &RpcBudget::new(4))
+            .await
+            .unwrap();
+        // the state, three walks and the ticks, each tick is read once
+        assert_eq!(rpc.calls("eth_call"), 5);
+        assert_eq!(tick_reads.load(Ordering::SeqCst), 2);
+
+        let pool = pools[0].get_v3().unwrap();
+        assert_eq!(pool.sqrt_price, U256::from(1u128 << 96));
+        assert_eq!((pool.tick, pool.liquidity), (0, 100));
+        assert_eq!(pool.ticks.len(), 2);
+        assert_eq!(pool.ticks[&-120].liquidity_net, 100);
+        assert_eq!(pool.ticks[&60].liquidity_net, -100);
+        assert_eq!(pool.ticks[&60].liquidity_gross, 100);
+        assert_eq!(pool.tick_bitmap, tick_bitmap([-120, 60], 60));
+    }
+}
@@ -0,0 +1,197 @@
+#[cfg(test)]
+mod store_test {
+    use alloy::primitives::{address, Address, U256};
+
+    use crate::errors::PoolSyncError;
+    use crate::pools::pool_structures::v3_structure::update_position;
+    use crate::store::format::split_header;
+    use crate::{
+        CacheEncoding, Chain, FileStore, MemoryStore, Pool, PoolCache, PoolInfo, PoolStore,
+        PoolType, SqliteStore, UniswapV2Pool, UniswapV3Pool, FORMAT_VERSION,
+    };
+
+    const POOL_A: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+    const POOL_B: Address = address!("0d4a11d5EEaaC28EC3F61d100daF4d40471f1852");
+
+    fn pool(address: Address, reserves: u128) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address,
+            token0_reserves: U256::from(reserves),
+            ..Default::default()
+        })
+    }
+
+    // only the dirty pools are written, removed ones are deleted
+    fn round_trip(store: &dyn PoolStore) {
+        let mut cache = PoolCache::new(PoolType::UniswapV2, 100);
+        assert!(store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().is_none());
+
+        cache.pools = vec![pool(POOL_A, 1), pool(POOL_B, 2)];
+        cache.mark_changed([POOL_A, POOL_B]);
+        cache.last_synced_block = 200;
+        store.upsert(Chain::Ethereum, &cache).unwrap();
+
+        cache.pools.retain(|pool| pool.address() != POOL_B);
+        cache.pools[0] = pool(POOL_A, 3);
+        cache.dirty.clear();
+        cache.mark_changed([POOL_A, POOL_B]);
+        cache.last_synced_block = 300;
+        store.upsert(Chain::Ethereum, &cache).unwrap();
+
+        let loaded = store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().unwrap();
+        assert_eq!(loaded.last_synced_block, 300);
+        assert_eq!(loaded.pools.len(), 1);
+        assert_eq!(loaded.pools[0].get_v2().unwrap().token0_reserves.to::<u128>(), 3);
+        assert_eq!(store.last_synced_block(Chain::Ethereum, PoolType::UniswapV2).unwrap(), Some(300));
+        assert!(store.load(Chain::Base, PoolType::UniswapV2).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_memory_store() {
+        round_trip(&MemoryStore::new());
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        round_trip(&SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("pool_sync_file_{}", std::process::id()));
+        round_trip(&FileStore::new(&dir).encoding(CacheEncoding::Binary).compressed(true));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_legacy_cache() {
+        // headerless JSON caches are read and rewritten in the current format
+        let dir = std::env::temp_dir().join(format!("pool_sync_legacy_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut cache = PoolCache::new(PoolType::UniswapV2, 100);
+        cache.pools = vec![pool(POOL_A, 1)];
+        let legacy = dir.join("Ethereum_UniswapV2_cache.json");
+        std::fs::write(&legacy, serde_json::to_vec(&cache).unwrap()).unwrap();
+
+        let store = FileStore::new(&dir);
+        let loaded = store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().unwrap();
+        assert_eq!(loaded.pools.len(), 1);
+        assert!(!legacy.exists());
+        assert!(dir.join("Ethereum_UniswapV2.cache").exists());
+        assert!(store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Cache file as written by format version 1, the header has no profile or checksum
+    fn v1_cache_file(encoding: &str, body: &[u8]) -> Vec<u8> {
+        let header = format!(
+            r#"{{"format_version":1,"chain":"Ethereum","pool_type":"UniswapV2","crate_version":"3.0.0","last_synced_block":100,"encoding":"{}","compression":"None"}}"#,
+            encoding
+        );
+        let mut file = b"POOLSYNC".to_vec();
+        file.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        file.extend_from_slice(header.as_bytes());
+        file.extend_from_slice(body);
+        file
+    }
+
+    #[test]
+    fn test_file_store_v1_cache() {
+        // version 1 caches of both encodings are read and rewritten with a checksum
+        let dir = std::env::temp_dir().join(format!("pool_sync_v1_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut cache = PoolCache::new(PoolType::UniswapV2, 100);
+        cache.pools = vec![pool(POOL_A, 1)];
+        cache.last_synced_block = 100;
+        let bodies = [
+            ("Json", serde_json::to_vec(&cache).unwrap()),
+            ("Binary", bincode::serialize(&cache).unwrap()),
+        ];
+
+        let path = dir.join("Ethereum_UniswapV2.cache");
+        for (encoding, body) in bodies {
+            std::fs::write(&path, v1_cache_file(encoding, &body)).unwrap();
+            let store = FileStore::new(&dir);
+            let loaded = store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().unwrap();
+            assert_eq!(loaded.last_synced_block, 100);
+            assert_eq!(loaded.pools[0].get_v2().unwrap().token0_reserves.to::<u128>(), 1);
+
+            let file = std::fs::read(&path).unwrap();
+            let (header, _) = split_header(&file).unwrap().unwrap();
+            assert_eq!(header.format_version, FORMAT_VERSION);
+            assert!(header.checksum.is_some());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_previous_generation() {
+        // a damaged cache fails its checksum and the generation it replaced is loaded instead
+        let dir = std::env::temp_dir().join(format!("pool_sync_generation_{}", std::process::id()));
+        let store = FileStore::new(&dir).encoding(CacheEncoding::Binary);
+        let mut cache = PoolCache::new(PoolType::UniswapV2, 100);
+        cache.pools = vec![pool(POOL_A, 1)];
+        cache.last_synced_block = 100;
+        store.upsert(Chain::Ethereum, &cache).unwrap();
+        cache.last_synced_block = 200;
+        store.upsert(Chain::Ethereum, &cache).unwrap();
+
+        let path = dir.join("Ethereum_UniswapV2.cache");
+        let mut file = std::fs::read(&path).unwrap();
+        *file.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, &file).unwrap();
+        let loaded = store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().unwrap();
+        assert_eq!(loaded.last_synced_block, 100);
+        let corrupt = dir.join("Ethereum_UniswapV2.cache.corrupt");
+        assert_eq!(loaded.recovered_from.as_ref(), Some(&corrupt));
+        assert_eq!(std::fs::read(&corrupt).unwrap(), file);
+
+        std::fs::write(&path, &file).unwrap();
+        assert!(matches!(
+            store.load(Chain::Ethereum, PoolType::UniswapV2),
+            Err(PoolSyncError::CacheChecksumMismatch { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_profiles() {
+        // profiles on the same directory never see each other's caches
+        let dir = std::env::temp_dir().join(format!("pool_sync_profiles_{}", std::process::id()));
+        let cache = PoolCache::new(PoolType::UniswapV2, 100);
+        FileStore::new(&dir).profile("a").upsert(Chain::Ethereum, &cache).unwrap();
+
+        let load = |store: FileStore| store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap();
+        assert!(load(FileStore::new(&dir).profile("a")).is_some());
+        assert!(load(FileStore::new(&dir).profile("b")).is_none());
+        assert!(load(FileStore::new(&dir)).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sqlite_v3_ticks() {
+        // ticks and bitmap words are written to their own tables and read back into the pool
+        let store = SqliteStore::open_in_memory().unwrap();
+        let mut pool = UniswapV3Pool {
+            address: POOL_A,
+            tick_spacing: 60,
+            sqrt_price: U256::from(1u64) << 96,
+            ..Default::default()
+        };
+        update_position(&mut pool, -120, 60, 100);
+        update_position(&mut pool, 0, 600, 50);
+
+        let mut cache = PoolCache::new(PoolType::UniswapV3, 100);
+        cache.pools = vec![Pool::UniswapV3(pool.clone())];
+        cache.mark_changed([POOL_A]);
+        store.upsert(Chain::Ethereum, &cache).unwrap();
+
+        let loaded = store.load(Chain::Ethereum, PoolType::UniswapV3).unwrap().unwrap();
+        let loaded = loaded.pools[0].get_v3().unwrap();
+        assert_eq!(loaded.sqrt_price, pool.sqrt_price);
+        assert_eq!(loaded.tick_bitmap, pool.tick_bitmap);
+        assert_eq!(loaded.ticks.len(), 4);
+        assert_eq!(loaded.ticks[&-120].liquidity_net, 100);
+        assert_eq!(loaded.ticks[&600].liquidity_net, -50);
+    }
+}
@@ -0,0 +1,283 @@
+#[cfg(test)]
+mod sync_test {
+    use alloy::dyn_abi::DynSolValue;
+    use alloy::primitives::aliases::U112;
+    use alloy::primitives::{address, Address, Bytes, U256};
+    use alloy::sol_types::SolEvent;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio_util::sync::CancellationToken;
+
+    use super::super::mock_rpc::mock::{self, MockRpc, Reply};
+    use crate::cache::{PendingPool, PoolCache, SyncPhase};
+    use crate::chain::Chain;
+    use crate::errors::PoolSyncError;
+    use crate::events::DataEvents;
+    use crate::live;
+    use crate::pools::gen::V2DataSync;
+    use crate::rpc::RpcBudget;
+    use crate::store::PoolStore;
+    use crate::tokens::TokenInfo;
+    use crate::{MemoryStore, Pool, PoolInfo, PoolSync, PoolType, UniswapV2Pool};
+
+    const POOL: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+    const PENDING_POOL: Address = address!("0d4a11d5EEaaC28EC3F61d100daF4d40471f1852");
+
+    /// Store that takes incremental writes and fails every one of them
+    struct FailingStore;
+
+    impl PoolStore for FailingStore {
+        fn load(&self, _: Chain, _: PoolType) -> Result<Option<PoolCache>, PoolSyncError> {
+            Ok(None)
+        }
+
+        fn upsert(&self, _: Chain, _: &PoolCache) -> Result<(), PoolSyncError> {
+            Err(PoolSyncError::StoreCorrupted("disk full".to_string()))
+        }
+
+        fn incremental(&self) -> bool {
+            true
+        }
+
+        fn load_tokens(&self, _: Chain) -> Result<Vec<TokenInfo>, PoolSyncError> {
+            Ok(Vec::new())
+        }
+
+        fn store_tokens(&self, _: Chain, _: &[&TokenInfo]) -> Result<(), PoolSyncError> {
+            Ok(())
+        }
+    }
+
+    /// Chain at block 100 where the pool emits a sync in every requested range, the first
+    /// blocks requested fail
+    fn chain(failed_blocks: usize) -> MockRpc {
+        chain_with_calls(failed_blocks, |_| Reply::Fail("unexpected request"))
+    }
+
+    /// Same chain with the eth_calls answered by `call`
+    fn chain_with_calls(
+        failed_blocks: usize,
+        call: impl Fn(&serde_json::Value) -> Reply + Send + Sync + 'static,
+    ) -> MockRpc {
+        let sync_topic = format!("{:?}", DataEvents::Sync::SIGNATURE_HASH);
+        let block_requests = AtomicUsize::new(0);
+        MockRpc::new(move |method, params| match method {
+            "eth_blockNumber" => Reply::Result(serde_json::json!("0x64")),
+            "eth_getLogs" if params.to_string().contains(&sync_topic) => {
+                let sync = DataEvents::Sync {
+                    reserve0: U112::from(110),
+                    reserve1: U112::from(190),
+                };
+                let log = mock::log(POOL, sync.encode_log_data(), mock::from_block(params));
+                Reply::Result(serde_json::json!([log]))
+            }
+            "eth_getLogs" => Reply::Result(serde_json::json!([])),
+            "eth_getBlockByNumber" if block_requests.fetch_add(1, Ordering::SeqCst) < failed_blocks => {
+                Reply::Fail("block unavailable")
+            }
+            "eth_getBlockByNumber" => {
+                let number = params[0].as_str().unwrap().trim_start_matches("0x");
+                Reply::Result(mock::block(u64::from_str_radix(number, 16).unwrap()))
+            }
+            "eth_call" => call(params),
+            _ => Reply::Fail("unexpected request"),
+        })
+    }
+
+    /// V2DataSync output for the pending pool
+    fn pending_pool_data() -> Bytes {
+        let pool = DynSolValue::Tuple(vec![
+            DynSolValue::Address(PENDING_POOL),
+            DynSolValue::Address(Address::with_last_byte(1)),
+            DynSolValue::Address(Address::with_last_byte(2)),
+            DynSolValue::Uint(U256::from(18), 8),
+            DynSolValue::Uint(U256::from(6), 8),
+            DynSolValue::Uint(U256::from(1000), 112),
+            DynSolValue::Uint(U256::from(2000), 112),
+        ]);
+        DynSolValue::Array(vec![pool]).abi_encode().into()
+    }
+
+    fn cache() -> PoolCache {
+        let mut cache = PoolCache::new(PoolType::UniswapV2, 100);
+        cache.is_initial_sync = false;
+        cache.pools.push(Pool::UniswapV2(UniswapV2Pool {
+            address: POOL,
+            token0_reserves: U256::from(100),
+            token1_reserves: U256::from(200),
+            ..Default::default()
+        }));
+        cache
+    }
+
+    #[tokio::test]
+    async fn test_sync_cache() {
+        let rpc = chain(0);
+        let chain_sync = mock::chain_sync(&rpc, Arc::new(MemoryStore::new()));
+        let mut cache = cache();
+
+        let synced = chain_sync
+            .sync_cache(&mut cache, 100, chain_sync.archive.clone(), chain_sync.archive.clone(), RpcBudget::new(4), None)
+            .await
+            .unwrap();
+        assert_eq!(synced.to_block, 100);
+        assert!(synced.updated_pools.contains(&POOL));
+        assert_eq!(cache.last_synced_block, 100);
+        assert!(!cache.block_window.is_empty());
+        let pool = cache.pools[0].get_v2().unwrap();
+        assert_eq!(pool.token0_reserves, U256::from(110));
+        assert_eq!(pool.token1_reserves, U256::from(190));
+    }
+
+    #[tokio::test]
+    async fn test_pending_pool_retry() {
+        // the pending pool reverts on the first sync and is built on the second
+        let data_syncs = AtomicUsize::new(0);
+        let rpc = chain_with_calls(0, move |params| {
+            let (_, input) = mock::call(params);
+            // token metadata is not needed for the pool to be built
+            if !input.starts_with(&V2DataSync::BYTECODE) {
+                return Reply::Error(3, "execution reverted");
+            }
+            match data_syncs.fetch_add(1, Ordering::SeqCst) {
+                0 => Reply::Error(3, "execution reverted"),
+                _ => Reply::Result(serde_json::json!(pending_pool_data())),
+            }
+        });
+        let chain_sync = mock::chain_sync(&rpc, Arc::new(MemoryStore::new()));
+        let mut cache = cache();
+        cache.pending_pools.push(PendingPool {
+            address: PENDING_POOL,
+            since_block: 50,
+        });
+
+        for end_block in [100, 101, 102] {
+            chain_sync
+                .sync_cache(&mut cache, end_block, chain_sync.archive.clone(), chain_sync.archive.clone(), RpcBudget::new(4), None)
+                .await
+                .unwrap();
+            let expected = if end_block == 100 { 0 } else { 1 };
+            let built = cache.pools.iter().filter(|pool| pool.address() == PENDING_POOL).count();
+            assert_eq!(built, expected);
+            assert_eq!(cache.pending_pools.len(), 1 - expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_checkpoint() {
+        // the sync is cancelled while the first batch of logs is applied, it stops after
+        // the batch and the resumed sync continues with the next one
+        let cancel = CancellationToken::new();
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let sync_topic = format!("{:?}", DataEvents::Sync::SIGNATURE_HASH);
+        let rpc = {
+            let (cancel, ranges) = (cancel.clone(), ranges.clone());
+            MockRpc::new(move |method, params| match method {
+                "eth_getLogs" if params.to_string().contains(&sync_topic) => {
+                    cancel.cancel();
+                    let from_block = mock::from_block(params);
+                    ranges.lock().unwrap().push(from_block);
+                    let sync = DataEvents::Sync {
+                        reserve0: U112::from(from_block),
+                        reserve1: U112::from(190),
+                    };
+                    Reply::Result(serde_json::json!([mock::log(POOL, sync.encode_log_data(), from_block)]))
+                }
+                "eth_getLogs" => Reply::Result(serde_json::json!([])),
+                "eth_getBlockByNumber" => {
+                    let number = params[0].as_str().unwrap().trim_start_matches("0x");
+                    Reply::Result(mock::block(u64::from_str_radix(number, 16).unwrap()))
+                }
+                _ => Reply::Fail("unexpected request"),
+            })
+        };
+        let store = Arc::new(MemoryStore::new());
+        let chain_sync = mock::chain_sync(&rpc, store.clone());
+        let mut cache = cache();
+        let end_block = 2_500_000;
+        let result = chain_sync
+            .sync_cache(&mut cache, end_block, chain_sync.archive.clone(), chain_sync.archive.clone(), RpcBudget::new(4), Some(&cancel))
+            .await;
+        assert!(matches!(result, Err(PoolSyncError::Cancelled)));
+
+        // the checkpoint is after the first batch and holds its logs
+        let checkpoint = cache.checkpoint.clone().unwrap();
+        assert_eq!(checkpoint.end_block, end_block);
+        let SyncPhase::Populating { next_block, .. } = checkpoint.phase else {
+            panic!("sync stopped before populating");
+        };
+        let applied = std::mem::take(&mut *ranges.lock().unwrap());
+        assert!(applied.iter().all(|from_block| *from_block < next_block));
+        let reserves = cache.pools[0].get_v2().unwrap().token0_reserves;
+        assert_eq!(reserves, U256::from(*applied.iter().max().unwrap()));
+        assert!(store.load(Chain::Ethereum, PoolType::UniswapV2).unwrap().unwrap().checkpoint.is_some());
+
+        // the resumed sync ignores the requested end block and only applies the rest
+        let synced = chain_sync
+            .sync_cache(&mut cache, 200, chain_sync.archive.clone(), chain_sync.archive.clone(), RpcBudget::new(4), Some(&CancellationToken::new()))
+            .await
+            .unwrap();
+        assert_eq!((synced.from_block, synced.to_block), (100, end_block));
+        let resumed = ranges.lock().unwrap().clone();
+        assert_eq!(resumed.iter().min(), Some(&next_block));
+        let reserves = cache.pools[0].get_v2().unwrap().token0_reserves;
+        assert_eq!(reserves, U256::from(*resumed.iter().max().unwrap()));
+        assert_eq!(cache.last_synced_block, end_block);
+        assert!(cache.checkpoint.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_sync_leaves_cache_unchanged() {
+        // the logs are applied and merged, then the incremental write fails
+        let rpc = chain(0);
+        let chain_sync = mock::chain_sync(&rpc, Arc::new(FailingStore));
+        let mut cache = cache();
+
+        let result = chain_sync
+            .sync_cache(&mut cache, 100, chain_sync.archive.clone(), chain_sync.archive.clone(), RpcBudget::new(4), None)
+            .await;
+        assert!(matches!(result, Err(PoolSyncError::StoreCorrupted(_))));
+        assert!(rpc.calls("eth_getBlockByNumber") > 0);
+        assert_eq!(cache.last_synced_block, 99);
+        assert!(cache.block_window.is_empty());
+        assert!(cache.dirty.is_empty());
+        let pool = cache.pools[0].get_v2().unwrap();
+        assert_eq!(pool.token0_reserves, U256::from(100));
+        assert_eq!(pool.token1_reserves, U256::from(200));
+    }
+
+    #[tokio::test]
+    async fn test_live_sync() {
+        // the first tick fails to fetch the block, the next one applies it
+        let rpc = chain(1);
+        let chain_sync = mock::chain_sync(&rpc, Arc::new(MemoryStore::new()));
+        let pool_sync = PoolSync {
+            chains: HashMap::from([(Chain::Ethereum, chain_sync)]),
+            rate_limit: 4,
+            poll_interval: Duration::from_millis(10),
+            tokens: Arc::new(Mutex::default()),
+        };
+        let handle = live::spawn(pool_sync, HashMap::from([(Chain::Ethereum, (vec![cache()], 99))]));
+        let mut updates = handle.subscribe();
+
+        let update = tokio::time::timeout(Duration::from_secs(5), updates.recv())
+            .await
+            .expect("no update was emitted")
+            .unwrap();
+        assert_eq!(rpc.calls("eth_getBlockByNumber"), 2);
+        assert_eq!(update.chain, Chain::Ethereum);
+        assert_eq!(update.pool_type, PoolType::UniswapV2);
+        assert_eq!((update.from_block, update.to_block), (100, 100));
+        assert!(update.new_pools.is_empty());
+        assert_eq!(update.updated_pools, vec![POOL]);
+
+        assert_eq!(handle.last_synced_block(Chain::Ethereum).await, Some(100));
+        let pools = handle.pools(Chain::Ethereum).await;
+        let pool = pools[0].get_v2().unwrap();
+        assert_eq!(pool.token0_reserves, U256::from(110));
+        assert_eq!(pool.token1_reserves, U256::from(190));
+        handle.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod token_test {
+    use crate::multicall::IMulticall3::CallResult;
+    use crate::tokens::decode_string;
+    use crate::{Chain, TokenInfo, TokenRegistry};
+    use alloy::primitives::{address, Bytes};
+    use alloy::sol_types::SolValue;
+
+    #[test]
+    fn test_decode_string() {
+        // standard tokens return a string, older ones like MKR a zero padded bytes32
+        let standard = CallResult {
+            success: true,
+            returnData: "Maker".to_string().abi_encode().into(),
+        };
+        assert_eq!(decode_string(&standard), Some(("Maker".to_string(), false)));
+
+        let mut word = [0u8; 32];
+        word[..3].copy_from_slice(b"MKR");
+        let bytes32 = CallResult {
+            success: true,
+            returnData: Bytes::copy_from_slice(&word),
+        };
+        assert_eq!(decode_string(&bytes32), Some(("MKR".to_string(), true)));
+
+        let reverted = CallResult {
+            success: false,
+            returnData: Bytes::new(),
+        };
+        assert_eq!(decode_string(&reverted), None);
+    }
+
+    #[test]
+    fn test_registry_chains() {
+        // the same address is a different token on every chain
+        let weth = address!("4200000000000000000000000000000000000006");
+        let mut registry = TokenRegistry::default();
+        registry.load(
+            Chain::Base,
+            vec![TokenInfo {
+                address: weth,
+                symbol: "WETH".to_string(),
+                decimals: 18,
+                ..Default::default()
+            }],
+        );
+        registry.load(Chain::Ethereum, Vec::new());
+
+        assert_eq!(registry.get(Chain::Base, weth).unwrap().symbol, "WETH");
+        assert!(registry.get(Chain::Ethereum, weth).is_none());
+        assert_eq!(registry.chain_tokens(Chain::Base).count(), 1);
+    }
+}
@@ -0,0 +1,443 @@
+//! Token Registry
+//!
+//! Metadata of every token seen in a pool is fetched once and kept in a `TokenRegistry`,
+//! keyed by chain and address. Symbols are read through the NameSync helper, which also
+//! understands tokens that return a `bytes32` symbol and does not revert for tokens
+//! without one. NameSync is not deployed, its code is placed at an unused address for the
+//! duration of the call. Providers that do not support state overrides get the symbols from
+//! plain `symbol` calls instead. The name, decimals and total supply are read with plain
+//! calls in the same block, any deviation from the ERC20 standard is recorded as a quirk.
+
+use alloy::eips::BlockId;
+use alloy::network::Network;
+use alloy::primitives::{address, Address, Bytes, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::state::{AccountOverride, StateOverride};
+use alloy::sol;
+use alloy::sol_types::{SolCall, SolValue};
+use alloy::transports::Transport;
+use futures::try_join;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::chain::Chain;
+use crate::errors::PoolSyncError;
+use crate::multicall::{self, IMulticall3::CallResult};
+use crate::pools::gen::NameSync;
+use crate::pools::{Pool, PoolInfo};
+use crate::rpc::RpcBudget;
+
+/// Address the NameSync code is placed at, nothing is deployed here
+const NAME_SYNC: Address = address!("00000000000000000000000000000000004e414d");
+
+/// Prefix NameSync gives the symbol of a token that has none
+const UNKNOWN_PREFIX: &str = "UNK_";
+
+sol! {
+    // the return data is decoded by hand to detect quirks
+    interface IERC20Metadata {
+        function symbol() external view returns (string);
+        function name() external view returns (string);
+        function decimals() external view returns (uint8);
+        function totalSupply() external view returns (uint256);
+    }
+}
+
+/// Deviation of a token from the ERC20 standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TokenQuirk {
+    /// `symbol` is missing or reverts
+    MissingSymbol,
+    /// `name` is missing or reverts
+    MissingName,
+    /// `name` returns a `bytes32` instead of a string
+    Bytes32Name,
+    /// `decimals` is missing, reverts or does not fit in a `uint8`
+    MissingDecimals,
+    /// `totalSupply` is missing or reverts
+    MissingTotalSupply,
+}
+
+/// Metadata of a single token
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub address: Address,
+    /// Symbol of the token, empty if it has none
+    pub symbol: String,
+    /// Name of the token, empty if it has none
+    pub name: String,
+    /// Decimals of the token, zero if it has none
+    pub decimals: u8,
+    /// Total supply at `supply_block`
+    pub total_supply: U256,
+    /// Block the metadata was read at
+    pub supply_block: u64,
+    /// Deviations from the ERC20 standard that were detected
+    pub quirks: Vec<TokenQuirk>,
+}
+
+impl TokenInfo {
+    /// Whether the quirk was detected for the token
+    pub fn has_quirk(&self, quirk: TokenQuirk) -> bool {
+        self.quirks.contains(&quirk)
+    }
+}
+
+/// Metadata of the tokens in the synced pools of every chain
+#[derive(Debug, Clone, Default)]
+pub struct TokenRegistry {
+    tokens: HashMap<(Chain, Address), TokenInfo>,
+}
+
+impl TokenRegistry {
+    /// Metadata of the token on the chain
+    pub fn get(&self, chain: Chain, address: Address) -> Option<&TokenInfo> {
+        self.tokens.get(&(chain, address))
+    }
+
+    /// Iterates over the tokens of the chain
+    pub fn chain_tokens(&self, chain: Chain) -> impl Iterator<Item = &TokenInfo> {
+        self.tokens
+            .iter()
+            .filter(move |((token_chain, _), _)| *token_chain == chain)
+            .map(|(_, info)| info)
+    }
+
+    /// Number of tokens across all chains
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Whether no token is known yet
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub(crate) fn contains(&self, chain: Chain, address: Address) -> bool {
+        self.tokens.contains_key(&(chain, address))
+    }
+
+    pub(crate) fn insert(&mut self, chain: Chain, info: TokenInfo) {
+        self.tokens.insert((chain, info.address), info);
+    }
+
+    /// Replaces the tokens of the chain
+    pub(crate) fn load(&mut self, chain: Chain, tokens: Vec<TokenInfo>) {
+        self.tokens.retain(|(token_chain, _), _| *token_chain != chain);
+        for info in tokens {
+            self.insert(chain, info);
+        }
+    }
+}
+
+/// Registers the tokens of the pools and copies their symbols onto the pools
+///
+/// Only tokens that are not in the registry yet are fetched, each of them once.
+pub(crate) async fn register_pools<'a, P, T, N>(
+    provider: &Arc<P>,
+    pools: impl IntoIterator<Item = &'a mut Pool>,
+    chain: Chain,
+    registry: &Mutex<TokenRegistry>,
+    budget: &RpcBudget,
+) -> Result<(), PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let pools: Vec<&mut Pool> = pools.into_iter().collect();
+    let missing: Vec<Address> = {
+        let registry = registry.lock().unwrap();
+        pools
+            .iter()
+            .flat_map(|pool| pool.token_addresses())
+            .filter(|token| !registry.contains(chain, *token))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    };
+    if !missing.is_empty() {
+        let tokens = fetch_tokens(provider, &missing, budget).await?;
+        let mut registry = registry.lock().unwrap();
+        for info in tokens {
+            registry.insert(chain, info);
+        }
+    }
+
+    let registry = registry.lock().unwrap();
+    let symbol = |token: &Address| {
+        registry
+            .get(chain, *token)
+            .map(|info| info.symbol.clone())
+            .unwrap_or_default()
+    };
+    for pool in pools {
+        Pool::update_token0_name(pool, symbol(&pool.token0_address()));
+        Pool::update_token1_name(pool, symbol(&pool.token1_address()));
+        if let Some(pool) = pool.get_balancer_mut() {
+            pool.additional_token_names = pool.additional_tokens.iter().map(symbol).collect();
+        } else if let Some(pool) = pool.get_curve_tri_mut() {
+            pool.token2_name = symbol(&pool.token2);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the metadata of the tokens at the latest block
+async fn fetch_tokens<P, T, N>(
+    provider: &Arc<P>,
+    tokens: &[Address],
+    budget: &RpcBudget,
+) -> Result<Vec<TokenInfo>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let block = {
+        let _permit = budget.acquire().await;
+        provider
+            .get_block_number()
+            .await
+            .map_err(|e| PoolSyncError::ProviderError(e.to_string()))?
+    };
+    let (symbols, metadata) = try_join!(
+        fetch_symbols(provider, tokens, block, budget),
+        fetch_metadata(provider, tokens, block, budget)
+    )?;
+
+    Ok(tokens
+        .iter()
+        .zip(metadata.chunks(3))
+        .map(|(token, results)| {
+            let mut info = TokenInfo {
+                address: *token,
+                supply_block: block,
+                ..Default::default()
+            };
+            match symbols.get(token) {
+                Some(symbol) if !symbol.is_empty() => info.symbol = symbol.clone(),
+                _ => info.quirks.push(TokenQuirk::MissingSymbol),
+            }
+            match decode_string(&results[0]) {
+                Some((name, false)) => info.name = name,
+                Some((name, true)) => {
+                    info.name = name;
+                    info.quirks.push(TokenQuirk::Bytes32Name);
+                }
+                None => info.quirks.push(TokenQuirk::MissingName),
+            }
+            match decode_word(&results[1]).and_then(|decimals| u8::try_from(decimals).ok()) {
+                Some(decimals) => info.decimals = decimals,
+                None => info.quirks.push(TokenQuirk::MissingDecimals),
+            }
+            match decode_word(&results[2]) {
+                Some(total_supply) => info.total_supply = total_supply,
+                None => info.quirks.push(TokenQuirk::MissingTotalSupply),
+            }
+            info
+        })
+        .collect())
+}
+
+/// Reads the name, decimals and total supply of every token, three results per token
+async fn fetch_metadata<P, T, N>(
+    provider: &Arc<P>,
+    tokens: &[Address],
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<Vec<CallResult>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let calls = tokens
+        .iter()
+        .flat_map(|token| {
+            [
+                multicall::call(*token, IERC20Metadata::nameCall {}.abi_encode(), true),
+                multicall::call(*token, IERC20Metadata::decimalsCall {}.abi_encode(), true),
+                multicall::call(*token, IERC20Metadata::totalSupplyCall {}.abi_encode(), true),
+            ]
+        })
+        .collect();
+    multicall::aggregate(
+        provider,
+        calls,
+        BlockId::number(block),
+        StateOverride::default(),
+        budget,
+    )
+    .await
+}
+
+/// Decodes a string return value, falling back to a `bytes32` one
+///
+/// The flag is set when the value was a `bytes32`.
+pub(crate) fn decode_string(result: &CallResult) -> Option<(String, bool)> {
+    if !result.success {
+        return None;
+    }
+    if let Ok(value) = String::abi_decode(&result.returnData, true) {
+        return Some((value, false));
+    }
+    if result.returnData.len() != 32 {
+        return None;
+    }
+    let bytes: Vec<u8> = result
+        .returnData
+        .iter()
+        .copied()
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8(bytes).ok().map(|value| (value, true))
+}
+
+/// Decodes a single word return value
+fn decode_word(result: &CallResult) -> Option<U256> {
+    let data: &Bytes = &result.returnData;
+    (result.success && data.len() == 32).then(|| U256::from_be_slice(data))
+}
+
+/// Reads the symbols of the tokens, two tokens per NameSync call
+///
+/// A token whose symbol cannot be decoded makes its whole call fail, so the tokens of
+/// failed calls are read again one by one. Tokens that still fail get an empty symbol.
+async fn fetch_symbols<P, T, N>(
+    provider: &Arc<P>,
+    tokens: &[Address],
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<HashMap<Address, String>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let mut symbols = HashMap::new();
+    let mut failed = Vec::new();
+    let pairs: Vec<(Address, Address)> = tokens
+        .chunks(2)
+        .map(|pair| (pair[0], *pair.last().unwrap()))
+        .collect();
+    let names = match sync_names(provider, &pairs, block, budget).await {
+        Ok(names) => names,
+        Err(e) => {
+            warn!("NameSync is not available, reading the symbols directly: {}", e);
+            return fetch_plain_symbols(provider, tokens, block, budget).await;
+        }
+    };
+    for ((token0, token1), names) in pairs.iter().zip(names) {
+        match names {
+            Some((name0, name1)) => {
+                symbols.insert(*token0, name0);
+                symbols.insert(*token1, name1);
+            }
+            None => failed.extend([*token0, *token1]),
+        }
+    }
+
+    if !failed.is_empty() {
+        failed.sort_unstable();
+        failed.dedup();
+        let singles: Vec<(Address, Address)> = failed.iter().map(|token| (*token, *token)).collect();
+        let names = sync_names(provider, &singles, block, budget).await?;
+        for (token, names) in failed.iter().zip(names) {
+            symbols.insert(*token, names.map(|(name, _)| name).unwrap_or_default());
+        }
+    }
+    Ok(symbols)
+}
+
+/// Reads the symbols of the tokens with plain calls, for providers without state overrides
+async fn fetch_plain_symbols<P, T, N>(
+    provider: &Arc<P>,
+    tokens: &[Address],
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<HashMap<Address, String>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let calls = tokens
+        .iter()
+        .map(|token| multicall::call(*token, IERC20Metadata::symbolCall {}.abi_encode(), true))
+        .collect();
+    let results = multicall::aggregate(
+        provider,
+        calls,
+        BlockId::number(block),
+        StateOverride::default(),
+        budget,
+    )
+    .await?;
+    Ok(tokens
+        .iter()
+        .zip(results)
+        .map(|(token, result)| {
+            let symbol = decode_string(&result).map(|(symbol, _)| symbol);
+            (*token, symbol.unwrap_or_default())
+        })
+        .collect())
+}
+
+/// Calls NameSync for every pair of tokens, `None` for the calls that failed
+async fn sync_names<P, T, N>(
+    provider: &Arc<P>,
+    pairs: &[(Address, Address)],
+    block: u64,
+    budget: &RpcBudget,
+) -> Result<Vec<Option<(String, String)>>, PoolSyncError>
+where
+    P: Provider<T, N> + 'static,
+    T: Transport + Clone,
+    N: Network,
+{
+    let calls = pairs
+        .iter()
+        .map(|(token0, token1)| {
+            let data = NameSync::syncNamesCall {
+                token0: *token0,
+                token1: *token1,
+            }
+            .abi_encode();
+            multicall::call(NAME_SYNC, data, true)
+        })
+        .collect();
+    let overrides = StateOverride::from_iter([(
+        NAME_SYNC,
+        AccountOverride {
+            code: Some(NameSync::DEPLOYED_BYTECODE.clone()),
+            ..Default::default()
+        },
+    )]);
+    let results =
+        multicall::aggregate(provider, calls, BlockId::number(block), overrides, budget).await?;
+
+    Ok(results
+        .into_iter()
+        .map(|result| {
+            let names = NameSync::syncNamesCall::abi_decode_returns(&result.returnData, true);
+            match names {
+                Ok(names) if result.success => {
+                    Some((known(names.token0_name), known(names.token1_name)))
+                }
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// Drops the placeholder NameSync returns for tokens without a symbol
+fn known(symbol: String) -> String {
+    if symbol.starts_with(UNKNOWN_PREFIX) {
+        String::new()
+    } else {
+        symbol
+    }
+}
@@ -1,8 +1,12 @@
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use once_cell::sync::Lazy;
+
+/// Progress bars of all pool types that are syncing at the same time
+static PROGRESS: Lazy<MultiProgress> = Lazy::new(MultiProgress::new);
 
 /// Creates a progress bar for visual feedback during synchronization
 pub fn create_progress_bar(total_steps: u64, info: String) -> ProgressBar {
-    let pb = ProgressBar::new(total_steps);
+    let pb = PROGRESS.add(ProgressBar::new(total_steps));
     pb.set_style(
         ProgressStyle::default_bar()
             .template(&format!(